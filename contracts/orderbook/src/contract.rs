@@ -8,6 +8,7 @@ use crate::{
 
 use abstract_app::AppContract;
 use cosmwasm_std::Response;
+use cw20::Cw20ReceiveMsg;
 
 /// The type of the result returned by your app's entry points.
 pub type OrderbookResult<T = Response> = Result<T, OrderbookError>;
@@ -19,12 +20,14 @@ pub type Orderbook = AppContract<
     OrderbookExecuteMsg,
     OrderbookQueryMsg,
     OrderbookMigrateMsg,
+    Cw20ReceiveMsg,
 >;
 
 const APP: Orderbook = Orderbook::new(ORDERBOOK_ID, APP_VERSION, None)
     .with_instantiate(handlers::instantiate_handler)
     .with_execute(handlers::execute_handler)
     .with_query(handlers::query_handler)
+    .with_receive(handlers::receive_handler)
     .with_migrate(handlers::migrate_handler)
     .with_module_ibc(handlers::ibc_handler)
     .with_dependencies(&[])
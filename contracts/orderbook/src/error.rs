@@ -1,9 +1,12 @@
+use crate::msg::Trail;
+
 use abstract_app::sdk::AbstractSdkError;
 use abstract_app::std::AbstractError;
 use abstract_app::AppError;
-use cosmwasm_std::StdError;
+use cosmwasm_std::{Decimal, StdError, Uint128};
 use cw_asset::AssetError;
 use cw_controllers::AdminError;
+use cw_utils::Expiration;
 use thiserror::Error;
 
 #[derive(Error, Debug, PartialEq)]
@@ -29,9 +32,6 @@ pub enum OrderbookError {
     #[error("{0}")]
     Payment(#[from] cw_utils::PaymentError),
 
-    #[error("Invalid side {0}")]
-    InvalidSide(String),
-
     #[error("Quantity must be greater than zero")]
     ZeroQuantity,
 
@@ -41,6 +41,81 @@ pub enum OrderbookError {
     #[error("Asset deposited does not match the market side")]
     IncorrectAsset,
 
+    #[error("Fee of {fee_bps} bps is above the maximum of {max_bps} bps")]
+    FeeTooHigh { fee_bps: u16, max_bps: u16 },
+
+    #[error("Fee tier thresholds have to be strictly ascending")]
+    UnsortedFeeTiers,
+
+    #[error("Only {available} {asset} is free in the balance")]
+    InsufficientBalance { asset: String, available: Uint128 },
+
+    #[error("Only {available} {asset} of fees is available to withdraw")]
+    InsufficientRevenue { asset: String, available: Uint128 },
+
+    #[error("Market {base}/{quote} is not listed")]
+    MarketNotFound { base: String, quote: String },
+
+    #[error("Market {base}/{quote} is already listed")]
+    MarketExists { base: String, quote: String },
+
+    #[error("Market {base}/{quote} is not accepting orders")]
+    MarketNotActive { base: String, quote: String },
+
+    #[error("Invalid market: {0}")]
+    InvalidMarket(String),
+
+    #[error("Price {price} is not a multiple of the tick size {tick_size}")]
+    InvalidTickSize { price: Decimal, tick_size: Decimal },
+
+    #[error("Quantity {quantity} is not a multiple of the lot size {lot_size}")]
+    InvalidLotSize {
+        quantity: Uint128,
+        lot_size: Uint128,
+    },
+
+    #[error("Quantity {quantity} is below the minimum order size {min_order_size}")]
+    OrderTooSmall {
+        quantity: Uint128,
+        min_order_size: Uint128,
+    },
+
+    #[error("No resting orders to match against in the {base}/{quote} book")]
+    EmptyBook { base: String, quote: String },
+
+    #[error("Order expiry {0} has already passed")]
+    AlreadyExpired(Expiration),
+
+    #[error("Post-only order at {price} would cross the best price of {best}")]
+    PostOnlyWouldCross { price: Decimal, best: Decimal },
+
+    #[error("Post-only orders have to be good till cancelled")]
+    PostOnlyNotResting,
+
+    #[error("Fill-or-kill order can't be filled entirely at its limit price")]
+    FillOrKillUnfilled,
+
+    #[error("Stop at {trigger_price} would trigger right away, the last price is {last_price}")]
+    StopWouldTrigger {
+        trigger_price: Decimal,
+        last_price: Decimal,
+    },
+
+    #[error("Trailing stops need a last traded price in {base}/{quote} to follow")]
+    NoLastPrice { base: String, quote: String },
+
+    #[error("Trail {0} has to be above zero and below the price it follows")]
+    InvalidTrail(Trail),
+
+    #[error("Order {0} not found")]
+    OrderNotFound(u64),
+
+    #[error("Only the owner can cancel order {0}")]
+    NotOrderOwner(u64),
+
+    #[error("Order {0} is no longer open")]
+    OrderClosed(u64),
+
     #[error("The IBC message is not authorized")]
     UnauthorizedIbcMessage,
 }
@@ -1,14 +1,26 @@
 use crate::{
     contract::{Orderbook, OrderbookResult},
-    msg::OrderbookExecuteMsg,
-    state::CONFIG,
+    msg::{FeeRecipient, OrderbookExecuteMsg, Side},
+    state::{FeeTier, Funding, Market, MarketStatus, CONFIG, MARKETS, MAX_FEE_BPS},
+    OrderbookError,
 };
 
-use abstract_app::traits::AbstractResponse;
-use cosmwasm_std::{DepsMut, Env, MessageInfo};
+use abstract_app::{
+    objects::AssetEntry,
+    traits::{AbstractNameService, AbstractResponse},
+};
+use cosmwasm_std::{Addr, Coin, Decimal, Deps, DepsMut, Env, MessageInfo, Storage, Uint128};
+use cw_asset::{Asset, AssetInfo};
+use cw_utils::PaymentError;
 
-mod limit;
-mod market;
+pub(crate) mod balance;
+mod cancel;
+pub(crate) mod limit;
+mod listing;
+pub(crate) mod market;
+mod matching;
+mod revenue;
+pub(crate) mod stop;
 
 pub fn execute_handler(
     deps: DepsMut,
@@ -18,36 +30,362 @@ pub fn execute_handler(
     msg: OrderbookExecuteMsg,
 ) -> OrderbookResult {
     match msg {
-        OrderbookExecuteMsg::UpdateConfig {} => update_config(deps, env, info, api),
-        OrderbookExecuteMsg::Reset {} => reset(deps, env, info, api),
+        OrderbookExecuteMsg::UpdateConfig {
+            maker_fee_bps,
+            taker_fee_bps,
+            fee_recipient,
+            fee_tiers,
+        } => update_config(
+            deps,
+            env,
+            info,
+            api,
+            maker_fee_bps,
+            taker_fee_bps,
+            fee_recipient,
+            fee_tiers,
+        ),
+        OrderbookExecuteMsg::Reset { market, limit } => {
+            cancel::reset(deps, env, api, info, market, limit)
+        }
+        OrderbookExecuteMsg::WithdrawFees { assets, recipient } => {
+            revenue::withdraw_fees(deps, env, api, info, assets, recipient)
+        }
+        OrderbookExecuteMsg::CreateMarket {
+            base,
+            quote,
+            tick_size,
+            lot_size,
+            min_order_size,
+        } => listing::create_market(
+            deps,
+            env,
+            api,
+            info,
+            base,
+            quote,
+            tick_size,
+            lot_size,
+            min_order_size,
+        ),
+        OrderbookExecuteMsg::UpdateMarket {
+            base,
+            quote,
+            status,
+            min_order_size,
+        } => listing::update_market(deps, env, api, info, base, quote, status, min_order_size),
+        OrderbookExecuteMsg::Deposit {} => {
+            balance::deposit(deps, env, api, info.sender, native_funds(&info.funds))
+        }
+        OrderbookExecuteMsg::Withdraw { asset, amount } => {
+            balance::withdraw(deps, env, api, info, asset, amount)
+        }
         OrderbookExecuteMsg::LimitOrder {
             base,
             quote,
             price,
             side,
-        } => limit::limit_order(deps, env, api, info, base, quote, price, side),
-        OrderbookExecuteMsg::MarketOrder { base, quote, side } => {
-            market::market_order(deps, env, api, info, base, quote, side)
+            expires,
+            from_balance,
+            post_only,
+            time_in_force,
+        } => limit::limit_order(
+            deps,
+            env,
+            api,
+            info.sender,
+            native_funds(&info.funds),
+            from_balance,
+            base,
+            quote,
+            price,
+            side,
+            expires.unwrap_or_default(),
+            post_only,
+            time_in_force.unwrap_or_default(),
+        ),
+        OrderbookExecuteMsg::MarketOrder {
+            base,
+            quote,
+            side,
+            from_balance,
+        } => market::market_order(
+            deps,
+            env,
+            api,
+            info.sender,
+            native_funds(&info.funds),
+            from_balance,
+            base,
+            quote,
+            side,
+        ),
+        OrderbookExecuteMsg::StopOrder {
+            base,
+            quote,
+            side,
+            trigger_price,
+            limit_price,
+            from_balance,
+        } => stop::stop_order(
+            deps,
+            env,
+            api,
+            info.sender,
+            native_funds(&info.funds),
+            from_balance,
+            base,
+            quote,
+            side,
+            trigger_price,
+            limit_price,
+        ),
+        OrderbookExecuteMsg::TrailingStop {
+            base,
+            quote,
+            side,
+            trail,
+            from_balance,
+        } => stop::trailing_stop(
+            deps,
+            env,
+            api,
+            info.sender,
+            native_funds(&info.funds),
+            from_balance,
+            base,
+            quote,
+            side,
+            trail,
+        ),
+        OrderbookExecuteMsg::TriggerStops { market } => {
+            stop::trigger_pending(deps, env, api, info, market)
+        }
+        OrderbookExecuteMsg::CancelOrder { order_id } => {
+            cancel::cancel_order(deps, env, api, info, order_id)
+        }
+        OrderbookExecuteMsg::CancelAllOrders { market } => {
+            cancel::cancel_all_orders(deps, env, api, info, market)
+        }
+        OrderbookExecuteMsg::PruneExpired { market, limit } => {
+            cancel::prune_expired(deps, env, api, info, market, limit)
         }
     }
 }
 
 /// Update the configuration of the app
+#[allow(clippy::too_many_arguments)]
 fn update_config(
     deps: DepsMut,
     _env: Env,
     msg_info: MessageInfo,
     api: Orderbook,
+    maker_fee_bps: Option<u16>,
+    taker_fee_bps: Option<u16>,
+    fee_recipient: Option<FeeRecipient>,
+    fee_tiers: Option<Vec<FeeTier>>,
 ) -> OrderbookResult {
     // Only the admin should be able to call this
     api.admin.assert_admin(deps.as_ref(), &msg_info.sender)?;
-    let mut _config = CONFIG.load(deps.storage)?;
+    let mut config = CONFIG.load(deps.storage)?;
+
+    let tier_fees = fee_tiers
+        .iter()
+        .flatten()
+        .flat_map(|tier| [tier.maker_fee_bps, tier.taker_fee_bps]);
+    for fee_bps in [maker_fee_bps, taker_fee_bps]
+        .into_iter()
+        .flatten()
+        .chain(tier_fees)
+    {
+        if fee_bps > MAX_FEE_BPS {
+            return Err(OrderbookError::FeeTooHigh {
+                fee_bps,
+                max_bps: MAX_FEE_BPS,
+            });
+        }
+    }
+
+    if let Some(maker_fee_bps) = maker_fee_bps {
+        config.maker_fee_bps = maker_fee_bps;
+    }
+    if let Some(taker_fee_bps) = taker_fee_bps {
+        config.taker_fee_bps = taker_fee_bps;
+    }
+    if let Some(fee_tiers) = fee_tiers {
+        if fee_tiers
+            .windows(2)
+            .any(|pair| pair[0].min_volume >= pair[1].min_volume)
+        {
+            return Err(OrderbookError::UnsortedFeeTiers);
+        }
+        config.fee_tiers = fee_tiers;
+    }
+    if let Some(fee_recipient) = fee_recipient {
+        config.fee_recipient = match fee_recipient {
+            FeeRecipient::Account => None,
+            FeeRecipient::Address(address) => Some(deps.api.addr_validate(&address)?),
+        };
+    }
+    CONFIG.save(deps.storage, &config)?;
 
     Ok(api.response("update_config"))
 }
 
-fn reset(deps: DepsMut, _env: Env, info: MessageInfo, api: Orderbook) -> OrderbookResult {
-    api.admin.assert_admin(deps.as_ref(), &info.sender)?;
+/// A market's ANS names along with the assets they resolve to on this chain
+pub struct Pair {
+    pub base: String,
+    pub quote: String,
+    pub base_asset: AssetInfo,
+    pub quote_asset: AssetInfo,
+}
+
+impl Pair {
+    pub fn resolve(
+        api: &Orderbook,
+        deps: Deps,
+        base: String,
+        quote: String,
+    ) -> OrderbookResult<Self> {
+        let ans = api.name_service(deps);
+        let base_asset = ans.query(&AssetEntry::new(&base))?;
+        let quote_asset = ans.query(&AssetEntry::new(&quote))?;
+
+        Ok(Pair {
+            base,
+            quote,
+            base_asset,
+            quote_asset,
+        })
+    }
+
+    /// Asset an order on `side` escrows: quote for buys, base for sells
+    pub fn escrow_asset(&self, side: Side) -> &AssetInfo {
+        match side {
+            Side::Buy => &self.quote_asset,
+            Side::Sell => &self.base_asset,
+        }
+    }
+
+    /// ANS name of the asset an order on `side` escrows, as balances are keyed by it
+    pub fn escrow_entry(&self, side: Side) -> AssetEntry {
+        match side {
+            Side::Buy => AssetEntry::new(&self.quote),
+            Side::Sell => AssetEntry::new(&self.base),
+        }
+    }
+
+    pub fn key(&self) -> (String, String) {
+        (self.base.clone(), self.quote.clone())
+    }
+}
+
+/// Load a listed market, failing unless it currently accepts new orders
+fn active_market(storage: &dyn Storage, base: &str, quote: &str) -> OrderbookResult<Market> {
+    let market = MARKETS
+        .may_load(storage, (base.to_string(), quote.to_string()))?
+        .ok_or_else(|| OrderbookError::MarketNotFound {
+            base: base.to_string(),
+            quote: quote.to_string(),
+        })?;
+
+    if market.status != MarketStatus::Active {
+        return Err(OrderbookError::MarketNotActive {
+            base: base.to_string(),
+            quote: quote.to_string(),
+        });
+    }
+
+    Ok(market)
+}
+
+/// Make sure a limit price sits on the market's tick grid
+fn check_tick_size(market: &Market, price: Decimal) -> OrderbookResult<()> {
+    if !(price % market.tick_size).is_zero() {
+        return Err(OrderbookError::InvalidTickSize {
+            price,
+            tick_size: market.tick_size,
+        });
+    }
+
+    Ok(())
+}
+
+/// Make sure an order's base quantity is a non-zero whole number of lots, not below the minimum
+fn check_order_size(market: &Market, quantity: Uint128) -> OrderbookResult<()> {
+    // a market without a minimum would otherwise take orders too small for a single lot
+    if quantity.is_zero() {
+        return Err(OrderbookError::ZeroQuantity);
+    }
+
+    if quantity < market.min_order_size {
+        return Err(OrderbookError::OrderTooSmall {
+            quantity,
+            min_order_size: market.min_order_size,
+        });
+    }
+
+    if !(quantity % market.lot_size).is_zero() {
+        return Err(OrderbookError::InvalidLotSize {
+            quantity,
+            lot_size: market.lot_size,
+        });
+    }
+
+    Ok(())
+}
+
+/// Round a base quantity down to a whole number of lots
+fn whole_lots(quantity: Uint128, lot_size: Uint128) -> Uint128 {
+    quantity - quantity % lot_size
+}
+
+/// Coins sent along with an order, as assets so they are handled the same way as cw20 deposits
+fn native_funds(funds: &[Coin]) -> Vec<Asset> {
+    funds.iter().map(Asset::from).collect()
+}
+
+/// Make sure the asset the order escrows, and only that asset, was sent along and return its amount
+fn verify_deposit(funds: &[Asset], asset: &AssetInfo) -> OrderbookResult<Uint128> {
+    let Some(deposit) = funds.iter().find(|funds| &funds.info == asset) else {
+        return Err(OrderbookError::IncorrectAsset);
+    };
+
+    if funds.len() > 1 {
+        return Err(PaymentError::MultipleDenoms {}.into());
+    }
+
+    if deposit.amount.is_zero() {
+        return Err(OrderbookError::ZeroQuantity);
+    }
+
+    Ok(deposit.amount)
+}
+
+/// Escrow an order on `side`, from the funds sent along or, given `from_balance`, from the
+/// sender's free balance. Returns the escrowed amount and where it came from
+fn take_escrow(
+    storage: &mut dyn Storage,
+    sender: &Addr,
+    funds: &[Asset],
+    from_balance: Option<Uint128>,
+    pair: &Pair,
+    side: Side,
+) -> OrderbookResult<(Uint128, Funding)> {
+    let Some(amount) = from_balance else {
+        let quantity = verify_deposit(funds, pair.escrow_asset(side))?;
+        return Ok((quantity, Funding::Transfer));
+    };
+
+    if !funds.is_empty() {
+        return Err(PaymentError::NonPayable {}.into());
+    }
+
+    if amount.is_zero() {
+        return Err(OrderbookError::ZeroQuantity);
+    }
+
+    balance::lock_balance(storage, sender, pair.escrow_entry(side).as_str(), amount)?;
 
-    Ok(api.response("reset"))
+    Ok((amount, Funding::Balance))
 }
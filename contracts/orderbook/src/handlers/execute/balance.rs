@@ -0,0 +1,94 @@
+use crate::{
+    contract::{Orderbook, OrderbookResult},
+    state::{credit_balance, BALANCES},
+    OrderbookError,
+};
+
+use super::matching::Settlement;
+
+use abstract_app::{
+    objects::AssetEntry,
+    sdk::TransferInterface,
+    traits::{AbstractNameService, AbstractResponse},
+};
+use cosmwasm_std::{Addr, DepsMut, Env, MessageInfo, Storage, Uint128};
+use cw_asset::Asset;
+use cw_utils::PaymentError;
+
+/// Move the funds into the account and credit them to the sender's balance under their ANS names
+pub fn deposit(
+    deps: DepsMut,
+    _env: Env,
+    api: Orderbook,
+    sender: Addr,
+    funds: Vec<Asset>,
+) -> OrderbookResult {
+    if funds.is_empty() {
+        return Err(PaymentError::NoFunds {}.into());
+    }
+
+    let ans = api.name_service(deps.as_ref());
+    let entries = funds
+        .iter()
+        .map(|asset| ans.query(&asset.info))
+        .collect::<Result<Vec<AssetEntry>, _>>()?;
+    for (entry, asset) in entries.iter().zip(funds.iter()) {
+        credit_balance(deps.storage, &sender, entry.as_str(), asset.amount)?;
+    }
+
+    let deposit = api.bank(deps.as_ref()).deposit(funds)?;
+
+    Ok(api
+        .response("deposit")
+        .add_attribute("account", sender)
+        .add_messages(deposit))
+}
+
+/// Send part of the sender's free balance back to it
+pub fn withdraw(
+    deps: DepsMut,
+    _env: Env,
+    api: Orderbook,
+    info: MessageInfo,
+    asset: String,
+    amount: Uint128,
+) -> OrderbookResult {
+    if amount.is_zero() {
+        return Err(OrderbookError::ZeroQuantity);
+    }
+
+    let entry = AssetEntry::new(&asset);
+    let asset_info = api.name_service(deps.as_ref()).query(&entry)?;
+    lock_balance(deps.storage, &info.sender, entry.as_str(), amount)?;
+
+    let mut settlement = Settlement::default();
+    settlement.pay(&info.sender, &asset_info, amount);
+    let transfer = settlement.into_msg(&api, deps.as_ref())?;
+
+    Ok(api
+        .response("withdraw")
+        .add_attribute("asset", entry.as_str())
+        .add_attribute("amount", amount)
+        .add_messages(transfer))
+}
+
+/// Take `amount` out of an account's free balance, to escrow an order or leave the account
+pub(super) fn lock_balance(
+    storage: &mut dyn Storage,
+    account: &Addr,
+    asset: &str,
+    amount: Uint128,
+) -> OrderbookResult<()> {
+    let key = (account.clone(), asset.to_string());
+    let available = BALANCES.may_load(storage, key.clone())?.unwrap_or_default();
+    if amount > available {
+        return Err(OrderbookError::InsufficientBalance {
+            asset: asset.to_string(),
+            available,
+        });
+    }
+
+    BALANCES.save(storage, key, &(available - amount))?;
+
+    Ok(())
+}
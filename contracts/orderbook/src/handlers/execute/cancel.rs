@@ -0,0 +1,410 @@
+use crate::{
+    contract::{Orderbook, OrderbookResult},
+    msg::Side,
+    state::{
+        price_key, untrack_expiry, BookKey, Order, OrderStatus, StopOrder, StopStatus, ASKS, BIDS,
+        EXPIRE_AT_HEIGHT, EXPIRE_AT_TIME, LAST_PRICE, OPEN_ORDERS, OPEN_STOPS, ORDERS, STOP_BUYS,
+        STOP_ORDERS, STOP_SELLS,
+    },
+    OrderbookError,
+};
+
+use super::{matching::Settlement, stop::close_stop, Pair};
+
+// Expired orders refunded per prune, each one costs a few writes and possibly a transfer
+const DEFAULT_PRUNE_LIMIT: u32 = 30;
+const MAX_PRUNE_LIMIT: u32 = 100;
+// Orders and stops refunded per reset, the same cost as a prune
+const DEFAULT_RESET_LIMIT: u32 = 30;
+const MAX_RESET_LIMIT: u32 = 100;
+
+use abstract_app::traits::AbstractResponse;
+use cosmwasm_schema::serde::{de::DeserializeOwned, Serialize};
+use cosmwasm_std::{
+    Addr, Deps, DepsMut, Env, Event, MessageInfo, Order as StorageOrder, StdResult, Storage,
+    Uint128,
+};
+use cw_storage_plus::{Bound, Map};
+
+pub fn cancel_order(
+    deps: DepsMut,
+    _env: Env,
+    api: Orderbook,
+    info: MessageInfo,
+    order_id: u64,
+) -> OrderbookResult {
+    // stops only get an order record once they trigger into a limit order
+    let Some(order) = ORDERS.may_load(deps.storage, order_id)? else {
+        return cancel_stop(deps, api, info, order_id);
+    };
+
+    if order.owner != info.sender {
+        return Err(OrderbookError::NotOrderOwner(order_id));
+    }
+
+    if !order.status.is_open() {
+        return Err(OrderbookError::OrderClosed(order_id));
+    }
+
+    let pair = Pair::resolve(&api, deps.as_ref(), order.base.clone(), order.quote.clone())?;
+    let mut settlement = Settlement::default();
+    let refunded = order.remaining_quantity;
+    remove_order(
+        deps.storage,
+        order,
+        &pair,
+        &mut settlement,
+        OrderStatus::Cancelled,
+    )?;
+
+    let refund = settlement.into_msg(&api, deps.as_ref())?;
+
+    Ok(api
+        .response("cancel_order")
+        .add_attribute("order_id", order_id.to_string())
+        .add_attribute("refunded", refunded)
+        .add_messages(refund))
+}
+
+/// Cancel a pending stop order and refund its escrow
+fn cancel_stop(deps: DepsMut, api: Orderbook, info: MessageInfo, order_id: u64) -> OrderbookResult {
+    let stop = STOP_ORDERS
+        .may_load(deps.storage, order_id)?
+        .ok_or(OrderbookError::OrderNotFound(order_id))?;
+
+    if stop.owner != info.sender {
+        return Err(OrderbookError::NotOrderOwner(order_id));
+    }
+
+    if stop.status != StopStatus::Pending {
+        return Err(OrderbookError::OrderClosed(order_id));
+    }
+
+    let pair = Pair::resolve(&api, deps.as_ref(), stop.base.clone(), stop.quote.clone())?;
+    let mut settlement = Settlement::default();
+    let refunded = stop.quantity;
+    remove_stop(deps.storage, stop, &pair, &mut settlement)?;
+
+    let refund = settlement.into_msg(&api, deps.as_ref())?;
+
+    Ok(api
+        .response("cancel_order")
+        .add_attribute("order_id", order_id.to_string())
+        .add_attribute("refunded", refunded)
+        .add_messages(refund))
+}
+
+pub fn cancel_all_orders(
+    deps: DepsMut,
+    _env: Env,
+    api: Orderbook,
+    info: MessageInfo,
+    market: Option<(String, String)>,
+) -> OrderbookResult {
+    let order_ids = owned_ids(deps.storage, OPEN_ORDERS, &info.sender, &market)?;
+    let stop_ids = owned_ids(deps.storage, OPEN_STOPS, &info.sender, &market)?;
+
+    // resolve each market once, however many orders the sender has in it
+    let mut pairs: Vec<Pair> = vec![];
+    let mut settlement = Settlement::default();
+    for order_id in order_ids.iter() {
+        let order = ORDERS.load(deps.storage, *order_id)?;
+        let index = cached_pair(&mut pairs, &api, deps.as_ref(), &order.base, &order.quote)?;
+        remove_order(
+            deps.storage,
+            order,
+            &pairs[index],
+            &mut settlement,
+            OrderStatus::Cancelled,
+        )?;
+    }
+    for order_id in stop_ids.iter() {
+        let stop = STOP_ORDERS.load(deps.storage, *order_id)?;
+        let index = cached_pair(&mut pairs, &api, deps.as_ref(), &stop.base, &stop.quote)?;
+        remove_stop(deps.storage, stop, &pairs[index], &mut settlement)?;
+    }
+
+    let refunds = settlement.into_msg(&api, deps.as_ref())?;
+
+    Ok(api
+        .response("cancel_all_orders")
+        .add_attribute("cancelled", (order_ids.len() + stop_ids.len()).to_string())
+        .add_messages(refunds))
+}
+
+/// Ids in an account's `index` of resting orders or pending stops, optionally only in `market`
+fn owned_ids(
+    storage: &dyn Storage,
+    index: Map<(Addr, u64), (String, String)>,
+    owner: &Addr,
+    market: &Option<(String, String)>,
+) -> StdResult<Vec<u64>> {
+    Ok(index
+        .prefix(owner.clone())
+        .range(storage, None, None, StorageOrder::Ascending)
+        .collect::<StdResult<Vec<_>>>()?
+        .into_iter()
+        .filter(|(_, order_market)| match market {
+            Some(market) => market == order_market,
+            None => true,
+        })
+        .map(|(order_id, _)| order_id)
+        .collect())
+}
+
+/// Admin method - take every resting and pending stop order off the book, or only those of one
+/// `(base, quote)` market, refund their escrow and forget the last traded price. At most `limit`
+/// orders go per call, a large book is reset over several transactions
+pub fn reset(
+    deps: DepsMut,
+    _env: Env,
+    api: Orderbook,
+    info: MessageInfo,
+    market: Option<(String, String)>,
+    limit: Option<u32>,
+) -> OrderbookResult {
+    api.admin.assert_admin(deps.as_ref(), &info.sender)?;
+
+    let limit = limit.unwrap_or(DEFAULT_RESET_LIMIT).min(MAX_RESET_LIMIT) as usize;
+
+    let mut order_ids = vec![];
+    for book in [BIDS, ASKS] {
+        let resting = scoped_ids(deps.storage, book, &market, limit - order_ids.len())?;
+        order_ids.extend(resting);
+    }
+
+    let mut stop_ids = vec![];
+    for index in [STOP_BUYS, STOP_SELLS] {
+        let left = limit - order_ids.len() - stop_ids.len();
+        stop_ids.extend(scoped_ids(deps.storage, index, &market, left)?);
+    }
+
+    let mut pairs: Vec<Pair> = vec![];
+    let mut settlement = Settlement::default();
+    let mut events = vec![];
+    for order_id in order_ids.iter() {
+        let order = ORDERS.load(deps.storage, *order_id)?;
+        let index = cached_pair(&mut pairs, &api, deps.as_ref(), &order.base, &order.quote)?;
+        events.push(refund_event("refund", &order, &pairs[index]));
+        remove_order(
+            deps.storage,
+            order,
+            &pairs[index],
+            &mut settlement,
+            OrderStatus::Cancelled,
+        )?;
+    }
+    for order_id in stop_ids.iter() {
+        let stop = STOP_ORDERS.load(deps.storage, *order_id)?;
+        let index = cached_pair(&mut pairs, &api, deps.as_ref(), &stop.base, &stop.quote)?;
+        events.push(
+            Event::new("refund")
+                .add_attribute("order_id", stop.id.to_string())
+                .add_attribute("owner", &stop.owner)
+                .add_attribute("asset", pairs[index].escrow_entry(stop.side).as_str())
+                .add_attribute("amount", stop.quantity),
+        );
+        remove_stop(deps.storage, stop, &pairs[index], &mut settlement)?;
+    }
+
+    // the last price goes once nothing is left in scope, until then the reset is resumed
+    let complete = scoped_ids(deps.storage, BIDS, &market, 1)?.is_empty()
+        && scoped_ids(deps.storage, ASKS, &market, 1)?.is_empty()
+        && scoped_ids(deps.storage, STOP_BUYS, &market, 1)?.is_empty()
+        && scoped_ids(deps.storage, STOP_SELLS, &market, 1)?.is_empty();
+    if complete {
+        match market {
+            Some(market) => LAST_PRICE.remove(deps.storage, market),
+            None => LAST_PRICE.clear(deps.storage),
+        }
+    }
+
+    let refunds = settlement.into_msg(&api, deps.as_ref())?;
+
+    Ok(api
+        .response("reset")
+        .add_attribute("cancelled", (order_ids.len() + stop_ids.len()).to_string())
+        .add_attribute("complete", complete.to_string())
+        .add_events(events)
+        .add_messages(refunds))
+}
+
+/// Ids of the first `limit` entries of a book or stop index, optionally only in `market`
+fn scoped_ids<T>(
+    storage: &dyn Storage,
+    index: Map<BookKey, T>,
+    market: &Option<(String, String)>,
+    limit: usize,
+) -> StdResult<Vec<u64>>
+where
+    T: Serialize + DeserializeOwned,
+{
+    match market {
+        Some(market) => index
+            .prefix(market.clone())
+            .keys(storage, None, None, StorageOrder::Ascending)
+            .take(limit)
+            .map(|key| key.map(|(_, order_id)| order_id))
+            .collect(),
+        None => index
+            .keys(storage, None, None, StorageOrder::Ascending)
+            .take(limit)
+            .map(|key| key.map(|(_, _, (_, order_id))| order_id))
+            .collect(),
+    }
+}
+
+/// Refund the expired orders of a market, at most `limit` of them so any backlog can be worked
+/// off over several transactions
+pub fn prune_expired(
+    deps: DepsMut,
+    env: Env,
+    api: Orderbook,
+    _info: MessageInfo,
+    market: (String, String),
+    limit: Option<u32>,
+) -> OrderbookResult {
+    let limit = limit.unwrap_or(DEFAULT_PRUNE_LIMIT).min(MAX_PRUNE_LIMIT) as usize;
+
+    // entries expire once the block reaches them, so everything up to now is due
+    let mut order_ids = vec![];
+    for (index, now) in [
+        (EXPIRE_AT_HEIGHT, env.block.height),
+        (EXPIRE_AT_TIME, env.block.time.nanos()),
+    ] {
+        let due = index
+            .prefix(market.clone())
+            .keys(
+                deps.storage,
+                None,
+                Some(Bound::inclusive((now, u64::MAX))),
+                StorageOrder::Ascending,
+            )
+            .take(limit - order_ids.len())
+            .map(|key| key.map(|(_, order_id)| order_id))
+            .collect::<StdResult<Vec<_>>>()?;
+        order_ids.extend(due);
+    }
+
+    let mut events = vec![];
+    let mut settlement = Settlement::default();
+    if !order_ids.is_empty() {
+        let pair = Pair::resolve(&api, deps.as_ref(), market.0, market.1)?;
+        for order_id in order_ids.iter() {
+            let order = ORDERS.load(deps.storage, *order_id)?;
+            events.push(refund_event("expire", &order, &pair));
+            remove_order(
+                deps.storage,
+                order,
+                &pair,
+                &mut settlement,
+                OrderStatus::Expired,
+            )?;
+        }
+    }
+
+    let refunds = settlement.into_msg(&api, deps.as_ref())?;
+
+    Ok(api
+        .response("prune_expired")
+        .add_attribute("expired", order_ids.len().to_string())
+        .add_events(events)
+        .add_messages(refunds))
+}
+
+/// Event telling indexers an order left the book and what it was refunded
+fn refund_event(name: &str, order: &Order, pair: &Pair) -> Event {
+    Event::new(name)
+        .add_attribute("order_id", order.id.to_string())
+        .add_attribute("owner", &order.owner)
+        .add_attribute("asset", pair.escrow_entry(order.side).as_str())
+        .add_attribute("amount", order.remaining_quantity)
+}
+
+/// Index of the `(base, quote)` market in `pairs`, resolving it the first time one of its orders
+/// comes up
+fn cached_pair(
+    pairs: &mut Vec<Pair>,
+    api: &Orderbook,
+    deps: Deps,
+    base: &str,
+    quote: &str,
+) -> OrderbookResult<usize> {
+    if let Some(index) = pairs
+        .iter()
+        .position(|pair| pair.base == base && pair.quote == quote)
+    {
+        return Ok(index);
+    }
+
+    pairs.push(Pair::resolve(
+        api,
+        deps,
+        base.to_string(),
+        quote.to_string(),
+    )?);
+    Ok(pairs.len() - 1)
+}
+
+/// Take an order off the book, close it with `status` and refund its unfilled escrow, to the
+/// owner's balance if it was placed from it
+pub(super) fn remove_order(
+    storage: &mut dyn Storage,
+    mut order: Order,
+    pair: &Pair,
+    settlement: &mut Settlement,
+    status: OrderStatus,
+) -> OrderbookResult<()> {
+    let book = match order.side {
+        Side::Buy => BIDS,
+        Side::Sell => ASKS,
+    };
+
+    book.remove(
+        storage,
+        (
+            order.base.clone(),
+            order.quote.clone(),
+            (price_key(order.side, order.price), order.id),
+        ),
+    );
+    OPEN_ORDERS.remove(storage, (order.owner.clone(), order.id));
+    untrack_expiry(storage, pair.key(), order.id, &order.expires);
+    settlement.pay_order(
+        storage,
+        order.funding,
+        &order.owner,
+        &pair.escrow_entry(order.side),
+        pair.escrow_asset(order.side),
+        order.remaining_quantity,
+    )?;
+
+    order.remaining_quantity = Uint128::zero();
+    order.status = status;
+    ORDERS.save(storage, order.id, &order)?;
+
+    Ok(())
+}
+
+/// Drop a pending stop from its index, cancel it and refund its escrow
+fn remove_stop(
+    storage: &mut dyn Storage,
+    mut stop: StopOrder,
+    pair: &Pair,
+    settlement: &mut Settlement,
+) -> OrderbookResult<()> {
+    close_stop(storage, &stop);
+    settlement.pay_order(
+        storage,
+        stop.funding,
+        &stop.owner,
+        &pair.escrow_entry(stop.side),
+        pair.escrow_asset(stop.side),
+        stop.quantity,
+    )?;
+
+    stop.status = StopStatus::Cancelled;
+    STOP_ORDERS.save(storage, stop.id, &stop)?;
+
+    Ok(())
+}
@@ -1,121 +1,330 @@
 use crate::{
     contract::{Orderbook, OrderbookResult},
-    state::{BidAsk, ASKS, BIDS},
+    msg::{PostOnly, Side, TimeInForce},
+    state::{
+        next_order_id, price_key, track_expiry, BidAsk, Funding, Market, Order, OrderStatus, ASKS,
+        BIDS, CONFIG, OPEN_ORDERS, ORDERS,
+    },
     OrderbookError,
 };
 
-use abstract_app::{
-    objects::AssetEntry,
-    sdk::TransferInterface,
-    traits::{AbstractNameService, AbstractResponse},
+use super::{
+    active_market, check_order_size, check_tick_size,
+    matching::{best_order, match_order, record_trades, Fees, Fill, Settlement},
+    stop, take_escrow, whole_lots, Pair,
 };
-use cosmwasm_std::{Decimal, DepsMut, Env, MessageInfo, Uint128};
 
-fn verify_deposit(info: MessageInfo, denom: &str) -> OrderbookResult<Uint128> {
-    if let Some(funds) = info.funds.iter().find(|coin| coin.denom == denom) {
-        println!("funds: {:?}", funds);
-        if funds.amount.is_zero() {
-            return Err(OrderbookError::ZeroQuantity);
-        }
-
-        Ok(funds.amount)
-    } else {
-        // TODO >> return the funds back to the sender
-        Err(OrderbookError::IncorrectAsset)
-    }
-}
+use abstract_app::{sdk::TransferInterface, traits::AbstractResponse};
+use cosmwasm_std::{Addr, BlockInfo, Decimal, DepsMut, Env, Storage, Uint128};
+use cw_asset::Asset;
+use cw_utils::Expiration;
 
 #[allow(clippy::too_many_arguments)]
 pub fn limit_order(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     api: Orderbook,
-    info: MessageInfo,
+    sender: Addr,
+    funds: Vec<Asset>,
+    from_balance: Option<Uint128>,
     base: String,
     quote: String,
-    price: Decimal,
-    side: String,
+    mut price: Decimal,
+    side: Side,
+    expires: Expiration,
+    post_only: Option<PostOnly>,
+    time_in_force: TimeInForce,
 ) -> OrderbookResult {
-    let sender = info.sender.clone();
-
     // println!(
     //     "limit_order: sender: {:?}, base: {:?}, quote: {:?}, price: {:?}, side: {:?}",
     //     sender, base, quote, price, side
     // );
 
-    // validate side
-    if &side != "buy" && &side != "sell" {
-        return Err(OrderbookError::InvalidSide(side));
-    }
-
     // validate price
     if price.is_zero() {
         return Err(OrderbookError::ZeroPrice);
     }
 
-    let bank = api.bank(deps.as_ref());
+    if expires.is_expired(&env.block) {
+        return Err(OrderbookError::AlreadyExpired(expires));
+    }
 
-    let ans = api.name_service(deps.as_ref());
-    let _base_asset = ans.query(&AssetEntry::new(&base))?;
-    let _quote_asset = ans.query(&AssetEntry::new(&quote))?;
-    // println!("base_asset: {:?}", base_asset);
-    // println!("quote_asset: {:?}", quote_asset);
+    let market = active_market(deps.storage, &base, &quote)?;
+    check_tick_size(&market, price)?;
 
-    let market = (base.clone(), quote.clone());
+    // resolve the ANS names to the assets actually sent and paid out
+    let pair = Pair::resolve(&api, deps.as_ref(), base.clone(), quote.clone())?;
 
-    // for buy orders, place the order in the bids using quote_asset
-    // for sell orders, place the order in the asks using base_asset
-    let deposit = if &side == "buy" {
-        // make sure quote_asset funds deposited
-        let quantity = verify_deposit(info.clone(), quote.as_str())?;
+    if let Some(post_only) = post_only {
+        if time_in_force != TimeInForce::GoodTillCancelled {
+            return Err(OrderbookError::PostOnlyNotResting);
+        }
+        price = post_only_price(
+            deps.storage,
+            &env.block,
+            &pair,
+            side,
+            price,
+            &market,
+            post_only,
+        )?;
+    }
 
-        let deposit = bank.deposit(info.funds)?;
+    // buy orders escrow quote_asset and rest in the bids, sell orders base_asset in the asks
+    let deposit_asset = pair.escrow_asset(side);
 
-        let bid = BidAsk {
-            account: sender.clone(),
-            price,
-            quantity,
-        };
-
-        // find by market pair key and push to vector of orders
-        let bids_by_market = BIDS.may_load(deps.storage, market.clone())?;
-        println!("bids_by_market: {:?}", bids_by_market);
-
-        if bids_by_market.is_none() {
-            BIDS.save(deps.storage, market.clone(), &vec![bid])?;
-        } else {
-            let mut bids = bids_by_market.unwrap_or(vec![]);
-            bids.push(bid);
-            BIDS.save(deps.storage, market.clone(), &bids)?;
+    // make sure the funds for the side of the order were deposited, or lock them from the balance
+    let (quantity, funding) =
+        take_escrow(deps.storage, &sender, &funds, from_balance, &pair, side)?;
+
+    // bids escrow quote, size them by the whole lots it buys at the limit price
+    let base_quantity = match side {
+        Side::Buy => whole_lots(quantity.div_floor(price), market.lot_size),
+        Side::Sell => quantity,
+    };
+    check_order_size(&market, base_quantity)?;
+
+    let deposit = match funding {
+        Funding::Transfer => api
+            .bank(deps.as_ref())
+            .deposit(vec![Asset::new(deposit_asset.clone(), quantity)])?,
+        Funding::Balance => vec![],
+    };
+
+    let order_id = next_order_id(deps.storage)?;
+
+    // cross the book before resting anything
+    let mut settlement = Settlement::default();
+    let fees = Fees::new(CONFIG.load(deps.storage)?, &env);
+    let (fills, remaining) = match_order(
+        deps.storage,
+        &fees,
+        order_id,
+        &sender,
+        funding,
+        &pair,
+        side,
+        Some(price),
+        &env.block,
+        market.lot_size,
+        quantity,
+        &mut settlement,
+    )?;
+
+    let (status, resting) = match time_in_force {
+        TimeInForce::GoodTillCancelled => {
+            let order = BidAsk {
+                id: order_id,
+                account: sender.clone(),
+                price,
+                quantity: remaining,
+                expires,
+            };
+            rest_remainder(
+                deps.storage,
+                &fees,
+                &pair,
+                side,
+                &order,
+                quantity,
+                funding,
+                market.lot_size,
+                &mut settlement,
+            )?
         }
+        TimeInForce::ImmediateOrCancel | TimeInForce::FillOrKill => {
+            // the order is done once its escrow can't take another lot at the limit price
+            let filled = !fees.can_fill(
+                deps.storage,
+                &sender,
+                false,
+                side,
+                remaining,
+                price,
+                market.lot_size,
+            )?;
+            if time_in_force == TimeInForce::FillOrKill && !filled {
+                return Err(OrderbookError::FillOrKillUnfilled);
+            }
+
+            // it never rests, whatever is left goes back to the sender
+            settlement.pay_order(
+                deps.storage,
+                funding,
+                &sender,
+                &pair.escrow_entry(side),
+                deposit_asset,
+                remaining,
+            )?;
+
+            let status = if filled {
+                OrderStatus::Filled
+            } else {
+                OrderStatus::Cancelled
+            };
+            (status, Uint128::zero())
+        }
+    };
+    ORDERS.save(
+        deps.storage,
+        order_id,
+        &Order {
+            id: order_id,
+            base: base.clone(),
+            quote: quote.clone(),
+            side,
+            owner: sender.clone(),
+            price,
+            original_quantity: quantity,
+            remaining_quantity: resting,
+            funding,
+            expires,
+            status,
+            created_at_height: env.block.height,
+            created_at: env.block.time,
+        },
+    )?;
+
+    record_trades(deps.storage, &env, &base, &quote, side, &sender, &fills)?;
 
-        deposit
+    // the trades may have moved the price through pending stops
+    let triggered =
+        stop::trigger_stops(deps.storage, &env, &pair, &market, &fees, &mut settlement)?;
+
+    let payouts = settlement.into_msg(&api, deps.as_ref())?;
+
+    Ok(api
+        .response("limit_order")
+        .add_attribute("order_id", order_id.to_string())
+        .add_attribute("price", price.to_string())
+        .add_attribute("time_in_force", time_in_force.to_string())
+        .add_messages(deposit)
+        .add_messages(payouts)
+        .add_events(fills.iter().map(Fill::event))
+        .add_events(triggered)
+        .add_attribute("fills", fills.len().to_string())
+        .add_attribute("remaining", remaining))
+}
+
+/// Rest what a good-till-cancelled order did not fill, returning its status and the amount that
+/// rests. A remainder that can't take another lot at the order's price is refunded instead
+#[allow(clippy::too_many_arguments)]
+pub(super) fn rest_remainder(
+    storage: &mut dyn Storage,
+    fees: &Fees,
+    pair: &Pair,
+    side: Side,
+    order: &BidAsk,
+    quantity: Uint128,
+    funding: Funding,
+    lot_size: Uint128,
+    settlement: &mut Settlement,
+) -> OrderbookResult<(OrderStatus, Uint128)> {
+    // once on the book the remainder pays the maker fee
+    if fees.can_fill(
+        storage,
+        &order.account,
+        true,
+        side,
+        order.quantity,
+        order.price,
+        lot_size,
+    )? {
+        rest_order(storage, pair.key(), side, order)?;
+        return Ok((resting_status(order.quantity, quantity), order.quantity));
+    }
+
+    settlement.pay_order(
+        storage,
+        funding,
+        &order.account,
+        &pair.escrow_entry(side),
+        pair.escrow_asset(side),
+        order.quantity,
+    )?;
+
+    let status = if order.quantity < quantity {
+        OrderStatus::Filled
     } else {
-        // make sure base_asset funds deposited
-        let quantity = verify_deposit(info.clone(), base.as_str())?;
+        OrderStatus::Cancelled
+    };
+    Ok((status, Uint128::zero()))
+}
 
-        let deposit = bank.deposit(info.funds)?;
+/// Rest the unfilled remainder of an order, its id puts it at the back of the queue for its price
+fn rest_order(
+    storage: &mut dyn Storage,
+    market: (String, String),
+    side: Side,
+    order: &BidAsk,
+) -> OrderbookResult<()> {
+    if order.quantity.is_zero() {
+        return Ok(());
+    }
 
-        let ask = BidAsk {
-            account: sender.clone(),
-            price,
-            quantity,
-        };
-
-        // find by market pair key and push to vector of orders
-        let asks_by_market = ASKS.may_load(deps.storage, market.clone())?;
-        println!("asks_by_market: {:?}", asks_by_market);
-
-        if asks_by_market.is_none() {
-            ASKS.save(deps.storage, market.clone(), &vec![ask])?;
-        } else {
-            let mut asks = asks_by_market.unwrap_or(vec![]);
-            asks.push(ask);
-            ASKS.save(deps.storage, market.clone(), &asks)?;
-        }
+    let book = if side == Side::Buy { BIDS } else { ASKS };
+    book.save(
+        storage,
+        (
+            market.0.clone(),
+            market.1.clone(),
+            (price_key(side, order.price), order.id),
+        ),
+        order,
+    )?;
+    OPEN_ORDERS.save(storage, (order.account.clone(), order.id), &market)?;
+    track_expiry(storage, market, order.id, side, &order.expires)?;
 
-        deposit
+    Ok(())
+}
+
+/// Status of an order that rests whatever it did not fill
+fn resting_status(remaining: Uint128, quantity: Uint128) -> OrderStatus {
+    if remaining.is_zero() {
+        OrderStatus::Filled
+    } else if remaining < quantity {
+        OrderStatus::PartiallyFilled
+    } else {
+        OrderStatus::Open
+    }
+}
+
+/// Price a post-only order rests at. One that would cross the other side of the book fails, or
+/// moves one tick behind its best price if it may be repriced.
+#[allow(clippy::too_many_arguments)]
+fn post_only_price(
+    storage: &dyn Storage,
+    block: &BlockInfo,
+    pair: &Pair,
+    side: Side,
+    price: Decimal,
+    market: &Market,
+    post_only: PostOnly,
+) -> OrderbookResult<Decimal> {
+    let opposite = if side == Side::Buy { ASKS } else { BIDS };
+    let Some(best) = best_order(storage, &opposite, pair.key(), block)? else {
+        return Ok(price);
+    };
+
+    let (crosses, repriced) = match side {
+        Side::Buy => (
+            price >= best.price,
+            best.price
+                .checked_sub(market.tick_size)
+                .ok()
+                .filter(|repriced| !repriced.is_zero()),
+        ),
+        Side::Sell => (price <= best.price, Some(best.price + market.tick_size)),
     };
+    if !crosses {
+        return Ok(price);
+    }
 
-    Ok(api.response("limit_order").add_messages(deposit))
+    match (post_only, repriced) {
+        (PostOnly::Reprice, Some(repriced)) => Ok(repriced),
+        _ => Err(OrderbookError::PostOnlyWouldCross {
+            price,
+            best: best.price,
+        }),
+    }
 }
@@ -0,0 +1,105 @@
+use crate::{
+    contract::{Orderbook, OrderbookResult},
+    state::{Market, MarketStatus, MARKETS},
+    OrderbookError,
+};
+
+use super::Pair;
+
+use abstract_app::{objects::AssetEntry, traits::AbstractResponse};
+use cosmwasm_std::{Decimal, DepsMut, Env, MessageInfo, Uint128};
+
+#[allow(clippy::too_many_arguments)]
+pub fn create_market(
+    deps: DepsMut,
+    env: Env,
+    api: Orderbook,
+    info: MessageInfo,
+    base: String,
+    quote: String,
+    tick_size: Decimal,
+    lot_size: Uint128,
+    min_order_size: Uint128,
+) -> OrderbookResult {
+    api.admin.assert_admin(deps.as_ref(), &info.sender)?;
+
+    if base == quote {
+        return Err(OrderbookError::InvalidMarket(
+            "base and quote must differ".to_string(),
+        ));
+    }
+    if tick_size.is_zero() {
+        return Err(OrderbookError::InvalidMarket(
+            "tick size must be greater than zero".to_string(),
+        ));
+    }
+    if lot_size.is_zero() {
+        return Err(OrderbookError::InvalidMarket(
+            "lot size must be greater than zero".to_string(),
+        ));
+    }
+
+    let key = (base.clone(), quote.clone());
+    if MARKETS.has(deps.storage, key.clone()) {
+        return Err(OrderbookError::MarketExists { base, quote });
+    }
+
+    // both assets have to be known to ANS before anything can be escrowed in them
+    Pair::resolve(&api, deps.as_ref(), base.clone(), quote.clone())?;
+
+    MARKETS.save(
+        deps.storage,
+        key,
+        &Market {
+            base: AssetEntry::new(&base),
+            quote: AssetEntry::new(&quote),
+            tick_size,
+            lot_size,
+            min_order_size,
+            status: MarketStatus::Active,
+            created_at: env.block.time,
+        },
+    )?;
+
+    Ok(api
+        .response("create_market")
+        .add_attribute("base", base)
+        .add_attribute("quote", quote))
+}
+
+/// Halt or resume a market and adjust its minimum order size. Tick and lot sizes stay fixed as
+/// resting orders were placed on their grid
+#[allow(clippy::too_many_arguments)]
+pub fn update_market(
+    deps: DepsMut,
+    _env: Env,
+    api: Orderbook,
+    info: MessageInfo,
+    base: String,
+    quote: String,
+    status: Option<MarketStatus>,
+    min_order_size: Option<Uint128>,
+) -> OrderbookResult {
+    api.admin.assert_admin(deps.as_ref(), &info.sender)?;
+
+    let key = (base.clone(), quote.clone());
+    let mut market = MARKETS
+        .may_load(deps.storage, key.clone())?
+        .ok_or_else(|| OrderbookError::MarketNotFound {
+            base: base.clone(),
+            quote: quote.clone(),
+        })?;
+
+    if let Some(status) = status {
+        market.status = status;
+    }
+    if let Some(min_order_size) = min_order_size {
+        market.min_order_size = min_order_size;
+    }
+    MARKETS.save(deps.storage, key, &market)?;
+
+    Ok(api
+        .response("update_market")
+        .add_attribute("base", base)
+        .add_attribute("quote", quote))
+}
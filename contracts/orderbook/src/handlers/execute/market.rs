@@ -1,27 +1,109 @@
 use crate::{
     contract::{Orderbook, OrderbookResult},
+    msg::Side,
+    state::{next_order_id, Funding, ASKS, BIDS, CONFIG},
     OrderbookError,
 };
 
-use abstract_app::traits::AbstractResponse;
-use cosmwasm_std::{DepsMut, Env, MessageInfo};
+use super::{
+    active_market, check_order_size,
+    matching::{best_order, match_order, record_trades, Fees, Fill, Settlement},
+    stop, take_escrow, whole_lots, Pair,
+};
+
+use abstract_app::{sdk::TransferInterface, traits::AbstractResponse};
+use cosmwasm_std::{Addr, DepsMut, Env, Uint128};
+use cw_asset::Asset;
 
 #[allow(clippy::too_many_arguments)]
 pub fn market_order(
-    _deps: DepsMut,
-    _env: Env,
+    deps: DepsMut,
+    env: Env,
     api: Orderbook,
-    _info: MessageInfo,
-    _base: String,
-    _quote: String,
-    side: String,
+    sender: Addr,
+    funds: Vec<Asset>,
+    from_balance: Option<Uint128>,
+    base: String,
+    quote: String,
+    side: Side,
 ) -> OrderbookResult {
-    // validate side
-    if &side != "buy" && &side != "sell" {
-        return Err(OrderbookError::InvalidSide(side));
-    }
+    let market = active_market(deps.storage, &base, &quote)?;
+
+    // resolve the ANS names to the assets actually sent and paid out
+    let pair = Pair::resolve(&api, deps.as_ref(), base.clone(), quote.clone())?;
+
+    // buy orders spend quote against the asks, sell orders spend base against the bids
+    let book = if side == Side::Buy { ASKS } else { BIDS };
+    let deposit_asset = pair.escrow_asset(side);
+
+    // make sure the funds for the side of the order were deposited, or lock them from the balance
+    let (quantity, funding) =
+        take_escrow(deps.storage, &sender, &funds, from_balance, &pair, side)?;
+
+    let Some(best) = best_order(deps.storage, &book, pair.key(), &env.block)? else {
+        return Err(OrderbookError::EmptyBook { base, quote });
+    };
+
+    // a market buy gets the most base at the best ask, so size it by that
+    let base_quantity = match side {
+        Side::Buy => whole_lots(quantity.div_floor(best.price), market.lot_size),
+        Side::Sell => quantity,
+    };
+    check_order_size(&market, base_quantity)?;
+
+    let deposit = match funding {
+        Funding::Transfer => api
+            .bank(deps.as_ref())
+            .deposit(vec![Asset::new(deposit_asset.clone(), quantity)])?,
+        Funding::Balance => vec![],
+    };
+
+    // market orders never rest, the id only ties the fills together
+    let order_id = next_order_id(deps.storage)?;
+
+    // sweep the book from the best price outward
+    let mut settlement = Settlement::default();
+    let fees = Fees::new(CONFIG.load(deps.storage)?, &env);
+    let (fills, remaining) = match_order(
+        deps.storage,
+        &fees,
+        order_id,
+        &sender,
+        funding,
+        &pair,
+        side,
+        None,
+        &env.block,
+        market.lot_size,
+        quantity,
+        &mut settlement,
+    )?;
+
+    // whatever is left goes back to the sender
+    settlement.pay_order(
+        deps.storage,
+        funding,
+        &sender,
+        &pair.escrow_entry(side),
+        deposit_asset,
+        remaining,
+    )?;
+
+    record_trades(deps.storage, &env, &base, &quote, side, &sender, &fills)?;
+
+    // the trades may have moved the price through pending stops
+    let triggered =
+        stop::trigger_stops(deps.storage, &env, &pair, &market, &fees, &mut settlement)?;
 
-    // for buy orders, sort asks by price and quantity
+    let payouts = settlement.into_msg(&api, deps.as_ref())?;
 
-    Ok(api.response("increment"))
+    Ok(api
+        .response("market_order")
+        .add_attribute("order_id", order_id.to_string())
+        .add_messages(deposit)
+        .add_messages(payouts)
+        .add_events(fills.iter().map(Fill::event))
+        .add_events(triggered)
+        .add_attribute("fills", fills.len().to_string())
+        .add_attribute("refunded", remaining))
 }
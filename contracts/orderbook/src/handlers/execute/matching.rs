@@ -0,0 +1,509 @@
+use crate::{
+    contract::{Orderbook, OrderbookResult},
+    msg::Side,
+    state::{
+        add_volume, credit_balance, rolling_volume, untrack_expiry, BidAsk, BookKey, Config,
+        Funding, OrderStatus, Trade, ASKS, BIDS, BPS_DENOMINATOR, LAST_PRICE, NEXT_TRADE_ID,
+        OPEN_ORDERS, ORDERS, PROTOCOL_REVENUE, TRADES, TRADE_HISTORY_SIZE,
+    },
+};
+
+use super::{cancel::remove_order, stop::follow_price, whole_lots, Pair};
+
+use abstract_app::{
+    objects::AssetEntry,
+    sdk::{Execution, TransferInterface},
+};
+use cosmwasm_std::{
+    Addr, BlockInfo, CosmosMsg, Decimal, Deps, Env, Event, Order as StorageOrder, StdError,
+    StdResult, Storage, Timestamp, Uint128,
+};
+use cw_asset::{Asset, AssetInfo};
+use cw_storage_plus::Map;
+
+/// A match between the incoming order and a single resting order.
+#[derive(Debug)]
+pub struct Fill {
+    pub maker_order_id: u64,
+    pub taker_order_id: u64,
+    pub maker: Addr,
+    pub price: Decimal,
+    pub base_quantity: Uint128,
+    pub quote_quantity: Uint128,
+    /// Quote the resting order paid in fees
+    pub maker_fee: Uint128,
+    /// Quote the incoming order paid in fees
+    pub taker_fee: Uint128,
+}
+
+impl Fill {
+    fn new(
+        taker_order_id: u64,
+        maker: &BidAsk,
+        base_quantity: Uint128,
+        maker_fee_bps: u16,
+        taker_fee_bps: u16,
+    ) -> Self {
+        let quote_quantity = base_quantity.mul_ceil(maker.price);
+
+        Fill {
+            maker_order_id: maker.id,
+            taker_order_id,
+            maker: maker.account.clone(),
+            price: maker.price,
+            base_quantity,
+            quote_quantity,
+            maker_fee: fee(quote_quantity, maker_fee_bps),
+            taker_fee: fee(quote_quantity, taker_fee_bps),
+        }
+    }
+
+    /// Event emitted for every fill so indexers can follow trades
+    pub fn event(&self) -> Event {
+        Event::new("fill")
+            .add_attribute("maker_order_id", self.maker_order_id.to_string())
+            .add_attribute("taker_order_id", self.taker_order_id.to_string())
+            .add_attribute("maker", &self.maker)
+            .add_attribute("price", self.price.to_string())
+            .add_attribute("base_quantity", self.base_quantity)
+            .add_attribute("quote_quantity", self.quote_quantity)
+            .add_attribute("maker_fee", self.maker_fee)
+            .add_attribute("taker_fee", self.taker_fee)
+    }
+}
+
+/// Funds owed from the account proxy once matching is done, merged per recipient and asset.
+#[derive(Default)]
+pub struct Settlement {
+    payouts: Vec<(Addr, AssetInfo, Uint128)>,
+}
+
+impl Settlement {
+    pub fn pay(&mut self, recipient: &Addr, asset: &AssetInfo, amount: Uint128) {
+        if amount.is_zero() {
+            return;
+        }
+
+        match self
+            .payouts
+            .iter_mut()
+            .find(|(to, info, _)| to == recipient && info == asset)
+        {
+            Some((_, _, total)) => *total += amount,
+            None => self
+                .payouts
+                .push((recipient.clone(), asset.clone(), amount)),
+        }
+    }
+
+    /// Pay the owner of an order in one of the pair's assets, to its balance if the order was
+    /// placed from it
+    pub fn pay_order(
+        &mut self,
+        storage: &mut dyn Storage,
+        funding: Funding,
+        recipient: &Addr,
+        entry: &AssetEntry,
+        asset: &AssetInfo,
+        amount: Uint128,
+    ) -> StdResult<()> {
+        match funding {
+            Funding::Transfer => self.pay(recipient, asset, amount),
+            Funding::Balance if !amount.is_zero() => {
+                credit_balance(storage, recipient, entry.as_str(), amount)?
+            }
+            Funding::Balance => {}
+        }
+
+        Ok(())
+    }
+
+    /// Build a single proxy execution that transfers every payout, if anything is owed.
+    pub fn into_msg(self, api: &Orderbook, deps: Deps) -> OrderbookResult<Option<CosmosMsg>> {
+        if self.payouts.is_empty() {
+            return Ok(None);
+        }
+
+        let bank = api.bank(deps);
+        let transfers = self
+            .payouts
+            .into_iter()
+            .map(|(recipient, info, amount)| {
+                bank.transfer(vec![Asset::new(info, amount)], &recipient)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Some(api.executor(deps).execute(transfers)?.into()))
+    }
+}
+
+/// Fee rates in force while matching, each account paying the rate of its volume tier
+pub struct Fees {
+    config: Config,
+    now: Timestamp,
+}
+
+impl Fees {
+    pub fn new(config: Config, env: &Env) -> Self {
+        Fees {
+            config,
+            now: env.block.time,
+        }
+    }
+
+    /// Maker and taker fee of `account` in basis points
+    fn rates(&self, storage: &dyn Storage, account: &Addr) -> StdResult<(u16, u16)> {
+        let volume = rolling_volume(storage, account, self.now)?;
+        Ok(self.config.fee_rates(volume))
+    }
+
+    /// Whether what is left of an order's escrow still pays for a lot at `price`, fee included.
+    /// Orders resting on the book pay the maker fee, incoming ones the taker fee
+    #[allow(clippy::too_many_arguments)]
+    pub fn can_fill(
+        &self,
+        storage: &dyn Storage,
+        account: &Addr,
+        resting: bool,
+        side: Side,
+        remaining: Uint128,
+        price: Decimal,
+        lot_size: Uint128,
+    ) -> StdResult<bool> {
+        match side {
+            Side::Buy => {
+                let (maker_fee_bps, taker_fee_bps) = self.rates(storage, account)?;
+                let fee_bps = if resting {
+                    maker_fee_bps
+                } else {
+                    taker_fee_bps
+                };
+                Ok(!max_base(remaining, price, fee_bps, lot_size).is_zero())
+            }
+            Side::Sell => Ok(remaining >= lot_size),
+        }
+    }
+}
+
+/// Cross an incoming order against the opposite side of the pair's book.
+///
+/// Resting orders are consumed best price first, then in order of arrival, as long as they are
+/// at or better than `limit`; market orders pass no limit and sweep the book. Fills execute at the
+/// resting order's price in whole `lot_size` lots of base and quote amounts are rounded up in
+/// favour of the party receiving quote. Both sides pay their fee in quote: the buyer on top of
+/// the fill, the seller out of its proceeds, each at the rate of its volume tier. `quantity` is
+/// what the taker escrowed: quote for buys, base for sells. Orders placed from a balance are paid
+/// to it rather than by transfer and expired resting orders are refunded and skipped. Returns the
+/// fills and the unmatched part of `quantity`.
+#[allow(clippy::too_many_arguments)]
+pub fn match_order(
+    storage: &mut dyn Storage,
+    fees: &Fees,
+    order_id: u64,
+    taker: &Addr,
+    funding: Funding,
+    pair: &Pair,
+    side: Side,
+    limit: Option<Decimal>,
+    block: &BlockInfo,
+    lot_size: Uint128,
+    quantity: Uint128,
+    settlement: &mut Settlement,
+) -> OrderbookResult<(Vec<Fill>, Uint128)> {
+    let market = pair.key();
+    let is_buy = side == Side::Buy;
+    let book = if is_buy { ASKS } else { BIDS };
+    let base_entry = AssetEntry::new(&pair.base);
+    let quote_entry = AssetEntry::new(&pair.quote);
+
+    let mut fills = vec![];
+    let mut remaining = quantity;
+    let (_, taker_fee_bps) = fees.rates(storage, taker)?;
+
+    while !remaining.is_zero() {
+        // the first entry of the market's prefix is always the best resting order
+        let Some((priority, mut maker)) = book
+            .prefix(market.clone())
+            .range(storage, None, None, StorageOrder::Ascending)
+            .next()
+            .transpose()?
+        else {
+            break;
+        };
+        let key = (market.0.clone(), market.1.clone(), priority);
+        let maker_order = ORDERS.load(storage, maker.id)?;
+
+        // expired orders are only taken off the book once something runs into them
+        if maker.expires.is_expired(block) {
+            remove_order(storage, maker_order, pair, settlement, OrderStatus::Expired)?;
+            continue;
+        }
+
+        let (maker_fee_bps, _) = fees.rates(storage, &maker.account)?;
+        let maker_funding = maker_order.funding;
+
+        let fill = if is_buy {
+            if limit.is_some_and(|limit| maker.price > limit) {
+                break;
+            }
+
+            // how many lots of base the remaining quote can buy at this level, fee included
+            let affordable = max_base(remaining, maker.price, taker_fee_bps, lot_size);
+            if affordable.is_zero() {
+                break;
+            }
+
+            let base_quantity = maker.quantity.min(affordable);
+            let fill = Fill::new(
+                order_id,
+                &maker,
+                base_quantity,
+                maker_fee_bps,
+                taker_fee_bps,
+            );
+
+            maker.quantity -= base_quantity;
+            remaining -= fill.quote_quantity + fill.taker_fee;
+
+            settlement.pay_order(
+                storage,
+                funding,
+                taker,
+                &base_entry,
+                &pair.base_asset,
+                base_quantity,
+            )?;
+            settlement.pay_order(
+                storage,
+                maker_funding,
+                &maker.account,
+                &quote_entry,
+                &pair.quote_asset,
+                fill.quote_quantity - fill.maker_fee,
+            )?;
+            fill
+        } else {
+            if limit.is_some_and(|limit| maker.price < limit) {
+                break;
+            }
+
+            // a bid that can no longer buy a single lot of base is dust, refund it
+            let capacity = max_base(maker.quantity, maker.price, maker_fee_bps, lot_size);
+            if capacity.is_zero() {
+                settlement.pay_order(
+                    storage,
+                    maker_funding,
+                    &maker.account,
+                    &quote_entry,
+                    &pair.quote_asset,
+                    maker.quantity,
+                )?;
+                maker.quantity = Uint128::zero();
+                // only an order that traded before counts as filled
+                let status = if maker_order.remaining_quantity < maker_order.original_quantity {
+                    OrderStatus::Filled
+                } else {
+                    OrderStatus::Cancelled
+                };
+                sync_order(storage, &book, key, &maker, status)?;
+                continue;
+            }
+
+            let base_quantity = remaining.min(capacity);
+            let fill = Fill::new(
+                order_id,
+                &maker,
+                base_quantity,
+                maker_fee_bps,
+                taker_fee_bps,
+            );
+
+            maker.quantity -= fill.quote_quantity + fill.maker_fee;
+            remaining -= base_quantity;
+
+            settlement.pay_order(
+                storage,
+                funding,
+                taker,
+                &quote_entry,
+                &pair.quote_asset,
+                fill.quote_quantity - fill.taker_fee,
+            )?;
+            settlement.pay_order(
+                storage,
+                maker_funding,
+                &maker.account,
+                &base_entry,
+                &pair.base_asset,
+                base_quantity,
+            )?;
+
+            if max_base(maker.quantity, maker.price, maker_fee_bps, lot_size).is_zero() {
+                settlement.pay_order(
+                    storage,
+                    maker_funding,
+                    &maker.account,
+                    &quote_entry,
+                    &pair.quote_asset,
+                    maker.quantity,
+                )?;
+                maker.quantity = Uint128::zero();
+            }
+            fill
+        };
+
+        // fees without a recipient stay in the account, booked apart from the escrow
+        let fee_total = fill.maker_fee + fill.taker_fee;
+        match &fees.config.fee_recipient {
+            Some(recipient) => settlement.pay(recipient, &pair.quote_asset, fee_total),
+            None if !fee_total.is_zero() => {
+                PROTOCOL_REVENUE.update(
+                    storage,
+                    AssetEntry::new(&pair.quote).to_string(),
+                    |revenue| -> StdResult<_> { Ok(revenue.unwrap_or_default() + fee_total) },
+                )?;
+            }
+            None => {}
+        }
+        fills.push(fill);
+
+        let status = if maker.quantity.is_zero() {
+            OrderStatus::Filled
+        } else {
+            OrderStatus::PartiallyFilled
+        };
+        sync_order(storage, &book, key, &maker, status)?;
+    }
+
+    Ok((fills, remaining))
+}
+
+/// Best order resting on one side of a market, passing over expired ones
+pub fn best_order(
+    storage: &dyn Storage,
+    book: &Map<BookKey, BidAsk>,
+    market: (String, String),
+    block: &BlockInfo,
+) -> StdResult<Option<BidAsk>> {
+    book.prefix(market)
+        .range(storage, None, None, StorageOrder::Ascending)
+        .map(|item| item.map(|(_, order)| order))
+        .find(|item| !matches!(item, Ok(order) if order.expires.is_expired(block)))
+        .transpose()
+}
+
+/// Fee owed on a quote amount, rounded down
+fn fee(quote_quantity: Uint128, fee_bps: u16) -> Uint128 {
+    quote_quantity.multiply_ratio(fee_bps, BPS_DENOMINATOR)
+}
+
+/// Most whole lots of base `budget` quote pays for at `price`, including a fee of `fee_bps`
+fn max_base(budget: Uint128, price: Decimal, fee_bps: u16, lot_size: Uint128) -> Uint128 {
+    let before_fee = budget.multiply_ratio(BPS_DENOMINATOR, BPS_DENOMINATOR + fee_bps);
+    let mut base = whole_lots(before_fee.div_floor(price), lot_size);
+
+    // rounding the quote up can push the estimate over budget by a lot
+    while !base.is_zero() {
+        let quote = base.mul_ceil(price);
+        if quote + fee(quote, fee_bps) <= budget {
+            break;
+        }
+        base -= lot_size;
+    }
+
+    base
+}
+
+/// Append the fills to the market's trade history, move its last traded price and count the
+/// traded quote towards both sides' fee tiers
+pub fn record_trades(
+    storage: &mut dyn Storage,
+    env: &Env,
+    base: &str,
+    quote: &str,
+    taker_side: Side,
+    taker: &Addr,
+    fills: &[Fill],
+) -> OrderbookResult<()> {
+    let Some(last) = fills.last() else {
+        return Ok(());
+    };
+    let market = (base.to_string(), quote.to_string());
+
+    let mut id = NEXT_TRADE_ID
+        .may_load(storage, market.clone())?
+        .unwrap_or(1);
+    for fill in fills {
+        TRADES.save(
+            storage,
+            (market.0.clone(), market.1.clone(), id),
+            &Trade {
+                id,
+                price: fill.price,
+                quantity: fill.base_quantity,
+                taker_side,
+                maker: fill.maker.clone(),
+                taker: taker.clone(),
+                block_height: env.block.height,
+                time: env.block.time,
+            },
+        )?;
+
+        // keep the history rolling
+        if id > TRADE_HISTORY_SIZE {
+            TRADES.remove(
+                storage,
+                (market.0.clone(), market.1.clone(), id - TRADE_HISTORY_SIZE),
+            );
+        }
+        id += 1;
+
+        add_volume(storage, &fill.maker, env.block.time, fill.quote_quantity)?;
+    }
+    NEXT_TRADE_ID.save(storage, market.clone(), &id)?;
+
+    let taker_volume = fills.iter().map(|fill| fill.quote_quantity).sum();
+    add_volume(storage, taker, env.block.time, taker_volume)?;
+
+    // trailing stops follow the best price printed
+    let prices = fills.iter().map(|fill| fill.price);
+    let high = prices.clone().max().unwrap_or(last.price);
+    let low = prices.min().unwrap_or(last.price);
+    follow_price(storage, market.clone(), high, low)?;
+
+    LAST_PRICE.save(storage, market, &last.price)?;
+
+    Ok(())
+}
+
+/// Write a resting order back to the book and its record with `status`, taking it off the book once
+/// fully consumed
+fn sync_order(
+    storage: &mut dyn Storage,
+    book: &Map<BookKey, BidAsk>,
+    key: BookKey,
+    resting: &BidAsk,
+    status: OrderStatus,
+) -> OrderbookResult<()> {
+    if resting.quantity.is_zero() {
+        untrack_expiry(
+            storage,
+            (key.0.clone(), key.1.clone()),
+            resting.id,
+            &resting.expires,
+        );
+        book.remove(storage, key);
+        OPEN_ORDERS.remove(storage, (resting.account.clone(), resting.id));
+    } else {
+        book.save(storage, key, resting)?;
+    }
+
+    ORDERS.update(storage, resting.id, |order| -> StdResult<_> {
+        let mut order = order.ok_or_else(|| StdError::not_found("order"))?;
+        order.remaining_quantity = resting.quantity;
+        order.status = status;
+        Ok(order)
+    })?;
+
+    Ok(())
+}
@@ -0,0 +1,51 @@
+use crate::{
+    contract::{Orderbook, OrderbookResult},
+    state::PROTOCOL_REVENUE,
+    OrderbookError,
+};
+
+use super::matching::Settlement;
+
+use abstract_app::{
+    objects::AnsAsset,
+    traits::{AbstractNameService, AbstractResponse},
+};
+use cosmwasm_std::{DepsMut, Env, MessageInfo};
+
+/// Send collected fees out of the account, never more than the ledger holds for each asset
+pub fn withdraw_fees(
+    deps: DepsMut,
+    _env: Env,
+    api: Orderbook,
+    info: MessageInfo,
+    assets: Vec<AnsAsset>,
+    recipient: String,
+) -> OrderbookResult {
+    api.admin.assert_admin(deps.as_ref(), &info.sender)?;
+    let recipient = deps.api.addr_validate(&recipient)?;
+
+    let mut settlement = Settlement::default();
+    for asset in assets.iter() {
+        let key = asset.name.to_string();
+        let available = PROTOCOL_REVENUE
+            .may_load(deps.storage, key.clone())?
+            .unwrap_or_default();
+        if asset.amount > available {
+            return Err(OrderbookError::InsufficientRevenue {
+                asset: key,
+                available,
+            });
+        }
+
+        let asset_info = api.name_service(deps.as_ref()).query(&asset.name)?;
+        PROTOCOL_REVENUE.save(deps.storage, key, &(available - asset.amount))?;
+        settlement.pay(&recipient, &asset_info, asset.amount);
+    }
+
+    let transfers = settlement.into_msg(&api, deps.as_ref())?;
+
+    Ok(api
+        .response("withdraw_fees")
+        .add_attribute("recipient", recipient)
+        .add_messages(transfers))
+}
@@ -0,0 +1,481 @@
+use crate::{
+    contract::{Orderbook, OrderbookResult},
+    msg::{Side, Trail},
+    state::{
+        next_order_id, stop_key, trail_key, BidAsk, Funding, Market, Order, StopOrder, StopStatus,
+        CONFIG, LAST_PRICE, OPEN_STOPS, ORDERS, STOP_BUYS, STOP_ORDERS, STOP_SELLS, TRAILING_BUYS,
+        TRAILING_SELLS,
+    },
+    OrderbookError,
+};
+
+use super::{
+    active_market, check_order_size, check_tick_size,
+    limit::rest_remainder,
+    matching::{match_order, record_trades, Fees, Fill, Settlement},
+    take_escrow, whole_lots, Pair,
+};
+
+use abstract_app::{sdk::TransferInterface, traits::AbstractResponse};
+use cosmwasm_std::{
+    Addr, CosmosMsg, Decimal, DepsMut, Env, Event, MessageInfo, Order as StorageOrder, StdResult,
+    Storage, Uint128,
+};
+use cw_asset::Asset;
+use cw_utils::Expiration;
+
+// Stops fired per transaction, each one matches against the book like a new order. Ones left
+// over stay crossed until the next trade or a `TriggerStops` call
+const MAX_STOP_TRIGGERS: usize = 10;
+// Trailing stops moved per trade, each one is re-keyed in two indexes
+const MAX_TRAIL_UPDATES: usize = 30;
+
+#[allow(clippy::too_many_arguments)]
+pub fn stop_order(
+    deps: DepsMut,
+    env: Env,
+    api: Orderbook,
+    sender: Addr,
+    funds: Vec<Asset>,
+    from_balance: Option<Uint128>,
+    base: String,
+    quote: String,
+    side: Side,
+    trigger_price: Decimal,
+    limit_price: Option<Decimal>,
+) -> OrderbookResult {
+    if trigger_price.is_zero() || limit_price.is_some_and(|price| price.is_zero()) {
+        return Err(OrderbookError::ZeroPrice);
+    }
+
+    let market = active_market(deps.storage, &base, &quote)?;
+    check_tick_size(&market, trigger_price)?;
+    if let Some(limit_price) = limit_price {
+        check_tick_size(&market, limit_price)?;
+    }
+
+    // a stop the market is already past would fire on placement, that's a plain order
+    if let Some(last_price) = LAST_PRICE.may_load(deps.storage, (base.clone(), quote.clone()))? {
+        let crossed = match side {
+            Side::Buy => last_price >= trigger_price,
+            Side::Sell => last_price <= trigger_price,
+        };
+        if crossed {
+            return Err(OrderbookError::StopWouldTrigger {
+                trigger_price,
+                last_price,
+            });
+        }
+    }
+
+    // resolve the ANS names to the assets actually sent and paid out
+    let pair = Pair::resolve(&api, deps.as_ref(), base, quote)?;
+    let (order_id, deposit) = place_stop(
+        deps,
+        &env,
+        &api,
+        sender,
+        &funds,
+        from_balance,
+        &market,
+        &pair,
+        side,
+        trigger_price,
+        limit_price,
+        None,
+    )?;
+
+    Ok(api
+        .response("stop_order")
+        .add_attribute("order_id", order_id.to_string())
+        .add_attribute("trigger_price", trigger_price.to_string())
+        .add_messages(deposit))
+}
+
+/// Place a trailing stop, following the market from its last traded price
+#[allow(clippy::too_many_arguments)]
+pub fn trailing_stop(
+    deps: DepsMut,
+    env: Env,
+    api: Orderbook,
+    sender: Addr,
+    funds: Vec<Asset>,
+    from_balance: Option<Uint128>,
+    base: String,
+    quote: String,
+    side: Side,
+    trail: Trail,
+) -> OrderbookResult {
+    let market = active_market(deps.storage, &base, &quote)?;
+    if let Trail::Offset(offset) = trail {
+        check_tick_size(&market, offset)?;
+    }
+
+    let Some(mark) = LAST_PRICE.may_load(deps.storage, (base.clone(), quote.clone()))? else {
+        return Err(OrderbookError::NoLastPrice { base, quote });
+    };
+    let trigger_price =
+        trail_trigger(trail, side, mark).ok_or(OrderbookError::InvalidTrail(trail))?;
+
+    // resolve the ANS names to the assets actually sent and paid out
+    let pair = Pair::resolve(&api, deps.as_ref(), base, quote)?;
+    let (order_id, deposit) = place_stop(
+        deps,
+        &env,
+        &api,
+        sender,
+        &funds,
+        from_balance,
+        &market,
+        &pair,
+        side,
+        trigger_price,
+        None,
+        Some((trail, mark)),
+    )?;
+
+    Ok(api
+        .response("trailing_stop")
+        .add_attribute("order_id", order_id.to_string())
+        .add_attribute("trail", trail.to_string())
+        .add_attribute("trigger_price", trigger_price.to_string())
+        .add_messages(deposit))
+}
+
+/// Escrow a stop and index it under its trigger, and under its mark if it trails. Returns its id
+/// and the messages depositing the escrow
+#[allow(clippy::too_many_arguments)]
+fn place_stop(
+    deps: DepsMut,
+    env: &Env,
+    api: &Orderbook,
+    sender: Addr,
+    funds: &[Asset],
+    from_balance: Option<Uint128>,
+    market: &Market,
+    pair: &Pair,
+    side: Side,
+    trigger_price: Decimal,
+    limit_price: Option<Decimal>,
+    trailing: Option<(Trail, Decimal)>,
+) -> OrderbookResult<(u64, Vec<CosmosMsg>)> {
+    let deposit_asset = pair.escrow_asset(side);
+
+    // the escrow is held until the stop fires or is cancelled
+    let (quantity, funding) = take_escrow(deps.storage, &sender, funds, from_balance, pair, side)?;
+
+    // buy stops escrow quote, size them by the lots it buys at the price they expect to fill at
+    let base_quantity = match side {
+        Side::Buy => whole_lots(
+            quantity.div_floor(limit_price.unwrap_or(trigger_price)),
+            market.lot_size,
+        ),
+        Side::Sell => quantity,
+    };
+    check_order_size(market, base_quantity)?;
+
+    let deposit = match funding {
+        Funding::Transfer => api
+            .bank(deps.as_ref())
+            .deposit(vec![Asset::new(deposit_asset.clone(), quantity)])?,
+        Funding::Balance => vec![],
+    };
+
+    // stops share ids with orders so the order a stop turns into keeps its id
+    let order_id = next_order_id(deps.storage)?;
+    STOP_ORDERS.save(
+        deps.storage,
+        order_id,
+        &StopOrder {
+            id: order_id,
+            base: pair.base.clone(),
+            quote: pair.quote.clone(),
+            side,
+            owner: sender.clone(),
+            trigger_price,
+            limit_price,
+            quantity,
+            funding,
+            trail: trailing.map(|(trail, _)| trail),
+            mark: trailing.map(|(_, mark)| mark),
+            status: StopStatus::Pending,
+            created_at: env.block.time,
+        },
+    )?;
+    OPEN_STOPS.save(deps.storage, (sender, order_id), &pair.key())?;
+    let (index, key) = stop_key(side, pair.key(), trigger_price, order_id);
+    index.save(deps.storage, key, &trigger_price)?;
+    if let Some((_, mark)) = trailing {
+        let (index, key) = trail_key(side, pair.key(), mark, order_id);
+        index.save(deps.storage, key, &mark)?;
+    }
+
+    Ok((order_id, deposit))
+}
+
+/// Fire the stops of a market that were crossed but left pending, callable by anyone
+pub fn trigger_pending(
+    deps: DepsMut,
+    env: Env,
+    api: Orderbook,
+    _info: MessageInfo,
+    market: (String, String),
+) -> OrderbookResult {
+    let (base, quote) = market;
+    let listing = active_market(deps.storage, &base, &quote)?;
+    let pair = Pair::resolve(&api, deps.as_ref(), base, quote)?;
+
+    let mut settlement = Settlement::default();
+    let fees = Fees::new(CONFIG.load(deps.storage)?, &env);
+    let events = trigger_stops(deps.storage, &env, &pair, &listing, &fees, &mut settlement)?;
+
+    let payouts = settlement.into_msg(&api, deps.as_ref())?;
+
+    Ok(api
+        .response("trigger_stops")
+        .add_attribute(
+            "triggered",
+            events
+                .iter()
+                .filter(|event| event.ty == "stop_triggered")
+                .count()
+                .to_string(),
+        )
+        .add_messages(payouts)
+        .add_events(events))
+}
+
+/// Turn the stops the market's last traded price crossed into orders, at most `MAX_STOP_TRIGGERS`
+/// of them. Each one trades against the book like a new order, which moves the last price again
+/// and may cross further stops. Returns the events of the fills and the fired stops.
+pub(super) fn trigger_stops(
+    storage: &mut dyn Storage,
+    env: &Env,
+    pair: &Pair,
+    market: &Market,
+    fees: &Fees,
+    settlement: &mut Settlement,
+) -> OrderbookResult<Vec<Event>> {
+    let mut events = vec![];
+
+    for _ in 0..MAX_STOP_TRIGGERS {
+        let Some(last_price) = LAST_PRICE.may_load(storage, pair.key())? else {
+            break;
+        };
+        let Some(order_id) = next_crossed(storage, pair.key(), last_price)? else {
+            break;
+        };
+
+        let mut stop = STOP_ORDERS.load(storage, order_id)?;
+        close_stop(storage, &stop);
+        stop.status = StopStatus::Triggered;
+        STOP_ORDERS.save(storage, order_id, &stop)?;
+
+        let (fills, remaining) = match_order(
+            storage,
+            fees,
+            order_id,
+            &stop.owner,
+            stop.funding,
+            pair,
+            stop.side,
+            stop.limit_price,
+            &env.block,
+            market.lot_size,
+            stop.quantity,
+            settlement,
+        )?;
+
+        match stop.limit_price {
+            // a stop-limit rests what it did not fill like any limit order
+            Some(price) => {
+                let order = BidAsk {
+                    id: order_id,
+                    account: stop.owner.clone(),
+                    price,
+                    quantity: remaining,
+                    expires: Expiration::Never {},
+                };
+                let (status, resting) = rest_remainder(
+                    storage,
+                    fees,
+                    pair,
+                    stop.side,
+                    &order,
+                    stop.quantity,
+                    stop.funding,
+                    market.lot_size,
+                    settlement,
+                )?;
+                ORDERS.save(
+                    storage,
+                    order_id,
+                    &Order {
+                        id: order_id,
+                        base: stop.base.clone(),
+                        quote: stop.quote.clone(),
+                        side: stop.side,
+                        owner: stop.owner.clone(),
+                        price,
+                        original_quantity: stop.quantity,
+                        remaining_quantity: resting,
+                        funding: stop.funding,
+                        expires: order.expires,
+                        status,
+                        created_at_height: env.block.height,
+                        created_at: env.block.time,
+                    },
+                )?;
+            }
+            // a stop-market never rests, whatever is left goes back to the owner
+            None => settlement.pay_order(
+                storage,
+                stop.funding,
+                &stop.owner,
+                &pair.escrow_entry(stop.side),
+                pair.escrow_asset(stop.side),
+                remaining,
+            )?,
+        }
+
+        record_trades(
+            storage,
+            env,
+            &stop.base,
+            &stop.quote,
+            stop.side,
+            &stop.owner,
+            &fills,
+        )?;
+
+        events.extend(fills.iter().map(Fill::event));
+        events.push(
+            Event::new("stop_triggered")
+                .add_attribute("order_id", order_id.to_string())
+                .add_attribute("trigger_price", stop.trigger_price.to_string())
+                .add_attribute("last_price", last_price.to_string())
+                .add_attribute("fills", fills.len().to_string())
+                .add_attribute("remaining", remaining),
+        );
+    }
+
+    Ok(events)
+}
+
+/// Id of the next stop of a market `last_price` crossed. Each side fires the trigger closest to
+/// the price first, as the first entry of its index; when both sides have one the earlier wins
+fn next_crossed(
+    storage: &dyn Storage,
+    market: (String, String),
+    last_price: Decimal,
+) -> StdResult<Option<u64>> {
+    let mut next: Option<u64> = None;
+    for (index, side) in [(STOP_BUYS, Side::Buy), (STOP_SELLS, Side::Sell)] {
+        let Some(((_, order_id), trigger_price)) = index
+            .prefix(market.clone())
+            .range(storage, None, None, StorageOrder::Ascending)
+            .next()
+            .transpose()?
+        else {
+            continue;
+        };
+
+        let crossed = match side {
+            Side::Buy => last_price >= trigger_price,
+            Side::Sell => last_price <= trigger_price,
+        };
+        if crossed && next.is_none_or(|next| order_id < next) {
+            next = Some(order_id);
+        }
+    }
+
+    Ok(next)
+}
+
+/// Move the marks of a market's trailing stops to the range its trades just printed, at most
+/// `MAX_TRAIL_UPDATES` of them, along with their triggers. Stops left behind catch up on the next
+/// trades.
+pub(super) fn follow_price(
+    storage: &mut dyn Storage,
+    market: (String, String),
+    high: Decimal,
+    low: Decimal,
+) -> StdResult<()> {
+    for (index, side, best) in [
+        (TRAILING_SELLS, Side::Sell, high),
+        (TRAILING_BUYS, Side::Buy, low),
+    ] {
+        // the first entries of a market are the marks furthest behind
+        let behind = index
+            .prefix(market.clone())
+            .range(storage, None, None, StorageOrder::Ascending)
+            .take_while(|item| match (item, side) {
+                (Ok((_, mark)), Side::Sell) => *mark < best,
+                (Ok((_, mark)), Side::Buy) => *mark > best,
+                (Err(_), _) => true,
+            })
+            .take(MAX_TRAIL_UPDATES)
+            .map(|item| item.map(|((_, order_id), _)| order_id))
+            .collect::<StdResult<Vec<_>>>()?;
+
+        for order_id in behind {
+            let mut stop = STOP_ORDERS.load(storage, order_id)?;
+            let Some(trail) = stop.trail else {
+                continue;
+            };
+            // a trail that no longer fits below the price keeps its trigger
+            let Some(trigger_price) = trail_trigger(trail, side, best) else {
+                continue;
+            };
+
+            unindex_stop(storage, &stop);
+            stop.mark = Some(best);
+            stop.trigger_price = trigger_price;
+            let (index, key) = stop_key(side, market.clone(), trigger_price, order_id);
+            index.save(storage, key, &trigger_price)?;
+            let (index, key) = trail_key(side, market.clone(), best, order_id);
+            index.save(storage, key, &best)?;
+            STOP_ORDERS.save(storage, order_id, &stop)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Trigger of a trailing stop following `mark`, `None` when the trail is empty or reaches zero
+fn trail_trigger(trail: Trail, side: Side, mark: Decimal) -> Option<Decimal> {
+    let hundred = Decimal::from_ratio(100u128, 1u128);
+    let distance = match trail {
+        Trail::Offset(offset) => offset,
+        Trail::Percent(percent) if percent < hundred => mark * percent / hundred,
+        Trail::Percent(_) => return None,
+    };
+    if distance.is_zero() {
+        return None;
+    }
+
+    match side {
+        Side::Buy => Some(mark + distance),
+        Side::Sell => mark
+            .checked_sub(distance)
+            .ok()
+            .filter(|price| !price.is_zero()),
+    }
+}
+
+/// Take a stop that fired or was cancelled out of its owner's pending stops and every index
+pub(super) fn close_stop(storage: &mut dyn Storage, stop: &StopOrder) {
+    OPEN_STOPS.remove(storage, (stop.owner.clone(), stop.id));
+    unindex_stop(storage, stop);
+}
+
+/// Take a stop out of its trigger index and, if it trails, its mark index
+fn unindex_stop(storage: &mut dyn Storage, stop: &StopOrder) {
+    let market = (stop.base.clone(), stop.quote.clone());
+    let (index, key) = stop_key(stop.side, market.clone(), stop.trigger_price, stop.id);
+    index.remove(storage, key);
+    if let Some(mark) = stop.mark {
+        let (index, key) = trail_key(stop.side, market, mark, stop.id);
+        index.remove(storage, key);
+    }
+}
@@ -1,48 +1,32 @@
-use crate::{
-    msg::{Header, OrderbookEvent, OrderbookIbcMessage},
-    Orderbook, OrderbookError, ORDERBOOK_ID,
-};
+use crate::{msg::OrderbookIbcMessage, Orderbook, OrderbookError, ORDERBOOK_ID};
 use abstract_adapter::sdk::AbstractResponse;
-use abstract_adapter::std::ibc::ModuleIbcMsg;
+use abstract_adapter::std::ibc::ModuleIbcInfo;
 use cosmwasm_std::{from_json, Binary, DepsMut, Env};
 
-use crate::{contract::OrderbookResult, handlers::execute::route_msg};
+use crate::contract::OrderbookResult;
 
 pub fn ibc_handler(
-    deps: DepsMut,
+    _deps: DepsMut,
     _env: Env,
-    mut app: Orderbook,
+    app: Orderbook,
     module_info: ModuleIbcInfo,
     ibc_msg: Binary,
 ) -> OrderbookResult {
-    // Assert IBC sender was the server
-    if module_info.module.id().ne(IBCMAIL_SERVER_ID) {
-        return Err(ServerError::UnauthorizedIbcModule(module_info.clone()));
+    // Assert IBC sender was another orderbook
+    if module_info.module.id().ne(ORDERBOOK_ID) {
+        return Err(OrderbookError::UnauthorizedIbcMessage);
     };
 
-    let server_msg: OrderbookIbcMessage = from_json(msg)?;
+    let orderbook_msg: OrderbookIbcMessage = from_json(ibc_msg)?;
 
-    match server_msg {
-        ServerIbcMessage::RouteMessage {
-            msg,
-            header,
-            metadata,
-        } => {
-            let msgs = route_message(
-                deps,
-                &env,
-                &mut app,
-                &TruncatedChainId::new(&env),
-                header,
-                metadata,
-                msg,
-            )?;
-
-            Ok(app
-                .response("module_ibc")
-                .add_attribute("method", "route")
-                .add_submessages(msgs))
-        }
-        _ => Err(ServerError::UnauthorizedIbcMessage {}),
+    match orderbook_msg {
+        OrderbookIbcMessage::RouteMessage { event, header } => Ok(app
+            .response("module_ibc")
+            .add_attribute("method", "route")
+            .add_attribute("current_hop", header.current_hop.to_string())
+            .add_attribute("base", event.base)
+            .add_attribute("quote", event.quote)
+            .add_attribute("side", event.side.to_string())
+            .add_attribute("order_type", event.order_type.to_string())),
     }
 }
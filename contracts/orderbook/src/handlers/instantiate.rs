@@ -13,7 +13,12 @@ pub fn instantiate_handler(
     _module: Orderbook,
     _msg: OrderbookInstantiateMsg,
 ) -> OrderbookResult {
-    let config: Config = Config {};
+    let config: Config = Config {
+        maker_fee_bps: 0,
+        taker_fee_bps: 0,
+        fee_recipient: None,
+        fee_tiers: vec![],
+    };
     CONFIG.save(deps.storage, &config)?;
 
     Ok(Response::new())
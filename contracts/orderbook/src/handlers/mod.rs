@@ -3,8 +3,9 @@ pub mod ibc;
 pub mod instantiate;
 pub mod migrate;
 pub mod query;
+pub mod receive;
 
 pub use self::{
     execute::execute_handler, ibc::ibc_handler, instantiate::instantiate_handler,
-    migrate::migrate_handler, query::query_handler,
+    migrate::migrate_handler, query::query_handler, receive::receive_handler,
 };
@@ -1,42 +1,469 @@
 use crate::{
     contract::{Orderbook, OrderbookResult},
-    msg::{AsksResponse, BidsResponse, ConfigResponse, OrderbookQueryMsg},
-    state::{ASKS, BIDS, CONFIG},
+    msg::{
+        AsksResponse, AssetBacking, BalancesResponse, BidsResponse, ConfigResponse, DepthLevel,
+        DepthResponse, FeeTierResponse, InvariantsResponse, LastPriceResponse, MarketsResponse,
+        OrderbookQueryMsg, OrdersResponse, ProtocolRevenueResponse, Side, StopsResponse,
+        TradesResponse,
+    },
+    state::{
+        price_key, rolling_volume, BidAsk, BookKey, Order as OrderRecord, OrderStatus, StopOrder,
+        ASKS, BALANCES, BIDS, CONFIG, LAST_PRICE, MARKETS, OPEN_ORDERS, OPEN_STOPS, ORDERS,
+        PROTOCOL_REVENUE, STOP_BUYS, STOP_ORDERS, STOP_SELLS, TRADES,
+    },
 };
 
-use cosmwasm_std::{to_json_binary, Binary, Deps, Env, Order, StdResult};
+use abstract_app::{
+    objects::{AnsAsset, AssetEntry},
+    traits::{AbstractNameService, AccountIdentification},
+};
+use cosmwasm_std::{
+    to_json_binary, Binary, BlockInfo, Decimal, Deps, Env, Order, StdResult, Uint128,
+};
+use cw_storage_plus::{Bound, Map};
+use std::collections::BTreeMap;
+
+// Page sizes for list queries, kept well below the query gas limit
+const DEFAULT_LIMIT: u32 = 30;
+const MAX_LIMIT: u32 = 100;
+const MAX_DEPTH_LEVELS: u32 = 50;
 
 pub fn query_handler(
     deps: Deps,
-    _env: Env,
-    _module: &Orderbook,
+    env: Env,
+    module: &Orderbook,
     msg: OrderbookQueryMsg,
 ) -> OrderbookResult<Binary> {
     match msg {
         OrderbookQueryMsg::Config {} => to_json_binary(&query_config(deps)?),
-        OrderbookQueryMsg::Bids {} => to_json_binary(&query_bids(deps)?),
-        OrderbookQueryMsg::Asks {} => to_json_binary(&query_asks(deps)?),
+        OrderbookQueryMsg::ProtocolRevenue {} => to_json_binary(&query_protocol_revenue(deps)?),
+        OrderbookQueryMsg::Invariants {} => to_json_binary(&query_invariants(deps, module)?),
+        OrderbookQueryMsg::Balances { owner } => to_json_binary(&query_balances(deps, owner)?),
+        OrderbookQueryMsg::FeeTier { account } => {
+            to_json_binary(&query_fee_tier(deps, env, account)?)
+        }
+        OrderbookQueryMsg::Markets { start_after, limit } => {
+            to_json_binary(&query_markets(deps, start_after, limit)?)
+        }
+        OrderbookQueryMsg::Bids {
+            base,
+            quote,
+            start_after,
+            limit,
+        } => to_json_binary(&query_bids(deps, env, base, quote, start_after, limit)?),
+        OrderbookQueryMsg::Asks {
+            base,
+            quote,
+            start_after,
+            limit,
+        } => to_json_binary(&query_asks(deps, env, base, quote, start_after, limit)?),
+        OrderbookQueryMsg::Depth {
+            base,
+            quote,
+            levels,
+        } => to_json_binary(&query_depth(deps, env, base, quote, levels)?),
+        OrderbookQueryMsg::LastPrice { base, quote } => {
+            to_json_binary(&query_last_price(deps, base, quote)?)
+        }
+        OrderbookQueryMsg::Trades {
+            base,
+            quote,
+            start_after,
+            limit,
+        } => to_json_binary(&query_trades(deps, base, quote, start_after, limit)?),
+        OrderbookQueryMsg::OpenOrders {
+            owner,
+            market,
+            start_after,
+            limit,
+        } => to_json_binary(&query_open_orders(
+            deps,
+            env,
+            owner,
+            market,
+            start_after,
+            limit,
+        )?),
+        OrderbookQueryMsg::Order { order_id } => {
+            to_json_binary(&query_order(deps, &env, order_id)?)
+        }
+        OrderbookQueryMsg::Stop { order_id } => to_json_binary(&query_stop_order(deps, order_id)?),
+        OrderbookQueryMsg::Stops {
+            owner,
+            market,
+            start_after,
+            limit,
+        } => to_json_binary(&query_stops(deps, owner, market, start_after, limit)?),
     }
     .map_err(Into::into)
 }
 
 fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
-    let _config = CONFIG.load(deps.storage)?;
-    Ok(ConfigResponse {})
+    let config = CONFIG.load(deps.storage)?;
+    Ok(ConfigResponse {
+        maker_fee_bps: config.maker_fee_bps,
+        taker_fee_bps: config.taker_fee_bps,
+        fee_recipient: config.fee_recipient,
+        fee_tiers: config.fee_tiers,
+    })
 }
 
-fn query_bids(deps: Deps) -> StdResult<BidsResponse> {
-    let bids = BIDS
+fn query_protocol_revenue(deps: Deps) -> StdResult<ProtocolRevenueResponse> {
+    let revenue = PROTOCOL_REVENUE
         .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(asset, amount)| AnsAsset::new(asset, amount)))
         .collect::<StdResult<Vec<_>>>()?;
 
-    Ok(BidsResponse { bids })
+    Ok(ProtocolRevenueResponse { revenue })
 }
 
-fn query_asks(deps: Deps) -> StdResult<AsksResponse> {
-    let asks = ASKS
+fn query_invariants(deps: Deps, module: &Orderbook) -> OrderbookResult<InvariantsResponse> {
+    let mut assets: BTreeMap<String, AssetBacking> = BTreeMap::new();
+
+    // bids escrow quote, asks escrow base
+    for item in BIDS.range(deps.storage, None, None, Order::Ascending) {
+        let ((_, quote, _), bid) = item?;
+        backing(&mut assets, &quote).locked += bid.quantity;
+    }
+    for item in ASKS.range(deps.storage, None, None, Order::Ascending) {
+        let ((base, _, _), ask) = item?;
+        backing(&mut assets, &base).locked += ask.quantity;
+    }
+    // pending stops hold their escrow off the book
+    for index in [STOP_BUYS, STOP_SELLS] {
+        for item in index.keys(deps.storage, None, None, Order::Ascending) {
+            let (_, _, (_, order_id)) = item?;
+            let stop = STOP_ORDERS.load(deps.storage, order_id)?;
+            let asset = match stop.side {
+                Side::Buy => &stop.quote,
+                Side::Sell => &stop.base,
+            };
+            backing(&mut assets, asset).locked += stop.quantity;
+        }
+    }
+    for item in BALANCES.range(deps.storage, None, None, Order::Ascending) {
+        let ((_, asset), amount) = item?;
+        backing(&mut assets, &asset).balances += amount;
+    }
+    for item in PROTOCOL_REVENUE.range(deps.storage, None, None, Order::Ascending) {
+        let (asset, amount) = item?;
+        backing(&mut assets, &asset).fees += amount;
+    }
+
+    let proxy = module.proxy_address(deps)?;
+    let ans = module.name_service(deps);
+    let mut shortfall = vec![];
+    for backing in assets.values_mut() {
+        let info = ans.query(&backing.asset)?;
+        backing.held = info.query_balance(&deps.querier, &proxy)?;
+
+        let total = backing.locked + backing.balances + backing.fees;
+        if total > backing.held {
+            shortfall.push(AnsAsset::new(backing.asset.clone(), total - backing.held));
+        }
+    }
+
+    Ok(InvariantsResponse {
+        assets: assets.into_values().collect(),
+        shortfall,
+    })
+}
+
+/// Running totals of an asset, keyed by its ANS name
+fn backing<'a>(
+    assets: &'a mut BTreeMap<String, AssetBacking>,
+    asset: &str,
+) -> &'a mut AssetBacking {
+    let asset = AssetEntry::new(asset);
+    assets
+        .entry(asset.to_string())
+        .or_insert_with(|| AssetBacking {
+            asset,
+            locked: Uint128::zero(),
+            balances: Uint128::zero(),
+            fees: Uint128::zero(),
+            held: Uint128::zero(),
+        })
+}
+
+fn query_balances(deps: Deps, owner: String) -> StdResult<BalancesResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let balances = BALANCES
+        .prefix(owner)
         .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(asset, amount)| AnsAsset::new(asset, amount)))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(BalancesResponse { balances })
+}
+
+fn query_fee_tier(deps: Deps, env: Env, account: String) -> StdResult<FeeTierResponse> {
+    let account = deps.api.addr_validate(&account)?;
+    let config = CONFIG.load(deps.storage)?;
+    let volume = rolling_volume(deps.storage, &account, env.block.time)?;
+    let (maker_fee_bps, taker_fee_bps) = config.fee_rates(volume);
+
+    Ok(FeeTierResponse {
+        volume,
+        tier: config.fee_tier(volume).map(|index| index as u32),
+        maker_fee_bps,
+        taker_fee_bps,
+    })
+}
+
+fn query_markets(
+    deps: Deps,
+    start_after: Option<(String, String)>,
+    limit: Option<u32>,
+) -> StdResult<MarketsResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let markets = MARKETS
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .map(|item| item.map(|(_, market)| market))
         .collect::<StdResult<Vec<_>>>()?;
 
+    Ok(MarketsResponse { markets })
+}
+
+fn query_bids(
+    deps: Deps,
+    env: Env,
+    base: String,
+    quote: String,
+    start_after: Option<(Decimal, u64)>,
+    limit: Option<u32>,
+) -> StdResult<BidsResponse> {
+    let bids = query_book(
+        deps,
+        &env.block,
+        BIDS,
+        Side::Buy,
+        base,
+        quote,
+        start_after,
+        limit,
+    )?;
+
+    Ok(BidsResponse { bids })
+}
+
+fn query_asks(
+    deps: Deps,
+    env: Env,
+    base: String,
+    quote: String,
+    start_after: Option<(Decimal, u64)>,
+    limit: Option<u32>,
+) -> StdResult<AsksResponse> {
+    let asks = query_book(
+        deps,
+        &env.block,
+        ASKS,
+        Side::Sell,
+        base,
+        quote,
+        start_after,
+        limit,
+    )?;
+
     Ok(AsksResponse { asks })
 }
+
+/// Page through one side of a market's book, best price first, leaving out expired orders
+#[allow(clippy::too_many_arguments)]
+fn query_book(
+    deps: Deps,
+    block: &BlockInfo,
+    book: Map<BookKey, BidAsk>,
+    side: Side,
+    base: String,
+    quote: String,
+    start_after: Option<(Decimal, u64)>,
+    limit: Option<u32>,
+) -> StdResult<Vec<BidAsk>> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(|(price, id)| Bound::exclusive((price_key(side, price), id)));
+
+    book.prefix((base, quote))
+        .range(deps.storage, start, None, Order::Ascending)
+        .map(|item| item.map(|(_, order)| order))
+        .filter(|item| !matches!(item, Ok(order) if order.expires.is_expired(block)))
+        .take(limit)
+        .collect()
+}
+
+fn query_depth(
+    deps: Deps,
+    env: Env,
+    base: String,
+    quote: String,
+    levels: u32,
+) -> StdResult<DepthResponse> {
+    let levels = levels.min(MAX_DEPTH_LEVELS) as usize;
+    let bids = query_levels(deps, &env.block, BIDS, Side::Buy, &base, &quote, levels)?;
+    let asks = query_levels(deps, &env.block, ASKS, Side::Sell, &base, &quote, levels)?;
+
+    let best_bid = bids.first().map(|level| level.price);
+    let best_ask = asks.first().map(|level| level.price);
+    let (mid, spread) = match (best_bid, best_ask) {
+        (Some(bid), Some(ask)) => (
+            Some((bid + ask) / Uint128::new(2)),
+            Some(ask.saturating_sub(bid)),
+        ),
+        _ => (None, None),
+    };
+
+    Ok(DepthResponse {
+        bids,
+        asks,
+        best_bid,
+        best_ask,
+        mid,
+        spread,
+    })
+}
+
+/// Aggregate the top `levels` prices of one side of a market's book, leaving out expired orders
+fn query_levels(
+    deps: Deps,
+    block: &BlockInfo,
+    book: Map<BookKey, BidAsk>,
+    side: Side,
+    base: &str,
+    quote: &str,
+    levels: usize,
+) -> StdResult<Vec<DepthLevel>> {
+    let mut depth: Vec<DepthLevel> = vec![];
+
+    for item in book.prefix((base.to_string(), quote.to_string())).range(
+        deps.storage,
+        None,
+        None,
+        Order::Ascending,
+    ) {
+        let (_, order) = item?;
+        if order.expires.is_expired(block) {
+            continue;
+        }
+
+        // bids escrow quote, express them in base like the asks
+        let quantity = if side == Side::Buy {
+            order.quantity.div_floor(order.price)
+        } else {
+            order.quantity
+        };
+
+        if let Some(level) = depth.last_mut().filter(|level| level.price == order.price) {
+            level.quantity += quantity;
+            level.orders += 1;
+            continue;
+        }
+
+        if depth.len() == levels {
+            break;
+        }
+
+        depth.push(DepthLevel {
+            price: order.price,
+            quantity,
+            orders: 1,
+        });
+    }
+
+    Ok(depth)
+}
+
+fn query_last_price(deps: Deps, base: String, quote: String) -> StdResult<LastPriceResponse> {
+    let price = LAST_PRICE.may_load(deps.storage, (base, quote))?;
+
+    Ok(LastPriceResponse { price })
+}
+
+fn query_trades(
+    deps: Deps,
+    base: String,
+    quote: String,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<TradesResponse> {
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let end = start_after.map(Bound::exclusive);
+
+    let trades = TRADES
+        .prefix((base, quote))
+        .range(deps.storage, None, end, Order::Descending)
+        .take(limit)
+        .map(|item| item.map(|(_, trade)| trade))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(TradesResponse { trades })
+}
+
+fn query_open_orders(
+    deps: Deps,
+    env: Env,
+    owner: String,
+    market: Option<(String, String)>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<OrdersResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let orders = OPEN_ORDERS
+        .prefix(owner)
+        .range(deps.storage, start, None, Order::Ascending)
+        .filter(|item| match (item, &market) {
+            (Ok((_, order_market)), Some(market)) => order_market == market,
+            _ => true,
+        })
+        .take(limit)
+        .map(|item| item.and_then(|(order_id, _)| query_order(deps, &env, order_id)))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(OrdersResponse { orders })
+}
+
+fn query_order(deps: Deps, env: &Env, order_id: u64) -> StdResult<OrderRecord> {
+    let mut order = ORDERS.load(deps.storage, order_id)?;
+
+    // an expired order stays on the book until it is matched against or pruned
+    if order.status.is_open() && order.expires.is_expired(&env.block) {
+        order.status = OrderStatus::Expired;
+    }
+
+    Ok(order)
+}
+
+fn query_stops(
+    deps: Deps,
+    owner: String,
+    market: Option<(String, String)>,
+    start_after: Option<u64>,
+    limit: Option<u32>,
+) -> StdResult<StopsResponse> {
+    let owner = deps.api.addr_validate(&owner)?;
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let start = start_after.map(Bound::exclusive);
+
+    let stops = OPEN_STOPS
+        .prefix(owner)
+        .range(deps.storage, start, None, Order::Ascending)
+        .filter(|item| match (item, &market) {
+            (Ok((_, stop_market)), Some(market)) => stop_market == market,
+            _ => true,
+        })
+        .take(limit)
+        .map(|item| item.and_then(|(order_id, _)| STOP_ORDERS.load(deps.storage, order_id)))
+        .collect::<StdResult<Vec<_>>>()?;
+
+    Ok(StopsResponse { stops })
+}
+
+fn query_stop_order(deps: Deps, order_id: u64) -> StdResult<StopOrder> {
+    STOP_ORDERS.load(deps.storage, order_id)
+}
@@ -0,0 +1,79 @@
+use crate::{
+    contract::{Orderbook, OrderbookResult},
+    handlers::execute::{balance, limit, market, stop},
+    msg::OrderbookReceiveMsg,
+};
+
+use cosmwasm_std::{from_json, DepsMut, Env, MessageInfo};
+use cw20::Cw20ReceiveMsg;
+use cw_asset::Asset;
+
+/// Handle cw20 tokens sent to the app, the embedded message says which order they pay for
+pub fn receive_handler(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    api: Orderbook,
+    msg: Cw20ReceiveMsg,
+) -> OrderbookResult {
+    let sender = deps.api.addr_validate(&msg.sender)?;
+    // the calling contract is the token that was sent
+    let funds = vec![Asset::cw20(info.sender, msg.amount)];
+
+    match from_json(&msg.msg)? {
+        OrderbookReceiveMsg::Deposit {} => balance::deposit(deps, env, api, sender, funds),
+        OrderbookReceiveMsg::LimitOrder {
+            base,
+            quote,
+            price,
+            side,
+            expires,
+            post_only,
+            time_in_force,
+        } => limit::limit_order(
+            deps,
+            env,
+            api,
+            sender,
+            funds,
+            None,
+            base,
+            quote,
+            price,
+            side,
+            expires.unwrap_or_default(),
+            post_only,
+            time_in_force.unwrap_or_default(),
+        ),
+        OrderbookReceiveMsg::MarketOrder { base, quote, side } => {
+            market::market_order(deps, env, api, sender, funds, None, base, quote, side)
+        }
+        OrderbookReceiveMsg::StopOrder {
+            base,
+            quote,
+            side,
+            trigger_price,
+            limit_price,
+        } => stop::stop_order(
+            deps,
+            env,
+            api,
+            sender,
+            funds,
+            None,
+            base,
+            quote,
+            side,
+            trigger_price,
+            limit_price,
+        ),
+        OrderbookReceiveMsg::TrailingStop {
+            base,
+            quote,
+            side,
+            trail,
+        } => stop::trailing_stop(
+            deps, env, api, sender, funds, None, base, quote, side, trail,
+        ),
+    }
+}
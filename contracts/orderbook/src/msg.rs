@@ -1,8 +1,13 @@
-use crate::{contract::Orderbook, state::BidAsk};
+use crate::{
+    contract::Orderbook,
+    state::{BidAsk, FeeTier, Market, MarketStatus, Order, StopOrder, Trade},
+};
 
-use abstract_app::objects::account::AccountTrace;
+use abstract_app::objects::{account::AccountTrace, AnsAsset, AssetEntry};
 use cosmwasm_schema::QueryResponses;
-use cosmwasm_std::Decimal;
+use cosmwasm_std::{Addr, Decimal, Uint128};
+use cw_utils::Expiration;
+use std::fmt;
 
 // This is used for type safety and re-exporting the contract endpoint structs.
 abstract_app::app_msg_types!(Orderbook, OrderbookExecuteMsg, OrderbookQueryMsg);
@@ -15,24 +20,244 @@ pub struct OrderbookInstantiateMsg {}
 #[cosmwasm_schema::cw_serde]
 #[derive(cw_orch::ExecuteFns)]
 pub enum OrderbookExecuteMsg {
-    UpdateConfig {},
+    /// Admin method - update the fee rates and where fees go, fields left out stay unchanged
+    UpdateConfig {
+        maker_fee_bps: Option<u16>,
+        taker_fee_bps: Option<u16>,
+        fee_recipient: Option<FeeRecipient>,
+        /// Replaces the whole tier table, thresholds have to be strictly ascending
+        fee_tiers: Option<Vec<FeeTier>>,
+    },
+    /// Admin method - send fees kept in the account to `recipient`
+    WithdrawFees {
+        assets: Vec<AnsAsset>,
+        recipient: String,
+    },
+    /// Admin method - list a market so orders can be placed in it
+    CreateMarket {
+        base: String,
+        quote: String,
+        tick_size: Decimal,
+        lot_size: Uint128,
+        min_order_size: Uint128,
+    },
+    /// Admin method - change the status or minimum order size of a listed market. Halted markets
+    /// take no new orders, resting ones can still be cancelled
+    UpdateMarket {
+        base: String,
+        quote: String,
+        status: Option<MarketStatus>,
+        min_order_size: Option<Uint128>,
+    },
+    /// Credit the funds sent along to the sender's balance, orders can then be placed from it
+    #[cw_orch(payable)]
+    Deposit {},
+    /// Send part of the sender's free balance back to it
+    Withdraw { asset: String, amount: Uint128 },
     /// Place a limit order
     #[cw_orch(payable)]
     LimitOrder {
         base: String,
         quote: String,
         price: Decimal,
-        side: String, // "buy" or "sell"
+        side: Side,
+        /// Stop matching and refund the order once this height or time is reached
+        expires: Option<Expiration>,
+        /// Lock this much of the sender's balance instead of sending funds, fills settle to it
+        from_balance: Option<Uint128>,
+        /// Never take liquidity, what happens to an order that would cross
+        post_only: Option<PostOnly>,
+        /// Good till cancelled when left out
+        time_in_force: Option<TimeInForce>,
     },
-    // Place a market order
+    /// Place a market order, filled immediately against the resting book
     #[cw_orch(payable)]
     MarketOrder {
         base: String,
         quote: String,
-        side: String, // "buy" or "sell"
+        side: Side,
+        /// Spend this much of the sender's balance instead of sending funds, fills settle to it
+        from_balance: Option<Uint128>,
+    },
+    /// Place a stop order, kept off the book until a trade reaches `trigger_price`, then turned
+    /// into a limit order at `limit_price` or a market order without one
+    #[cw_orch(payable)]
+    StopOrder {
+        base: String,
+        quote: String,
+        side: Side,
+        trigger_price: Decimal,
+        limit_price: Option<Decimal>,
+        /// Lock this much of the sender's balance instead of sending funds, fills settle to it
+        from_balance: Option<Uint128>,
+    },
+    /// Place a trailing stop, a stop-market order whose trigger follows the best traded price
+    /// since placement at `trail` behind it: below the high for sells, above the low for buys
+    #[cw_orch(payable)]
+    TrailingStop {
+        base: String,
+        quote: String,
+        side: Side,
+        trail: Trail,
+        /// Lock this much of the sender's balance instead of sending funds, fills settle to it
+        from_balance: Option<Uint128>,
+    },
+    /// Fire the stops of a `(base, quote)` market the last trades crossed but that were left
+    /// pending by the per transaction limit, callable by anyone
+    TriggerStops { market: (String, String) },
+    /// Cancel a resting order or pending stop and refund its unfilled escrow, only callable by its
+    /// owner
+    CancelOrder { order_id: u64 },
+    /// Cancel all of the sender's resting orders and pending stops, optionally only in one
+    /// `(base, quote)` market
+    CancelAllOrders { market: Option<(String, String)> },
+    /// Refund up to `limit` expired orders of a `(base, quote)` market, callable by anyone
+    PruneExpired {
+        market: (String, String),
+        limit: Option<u32>,
+    },
+    /// Admin method - cancel every resting order and pending stop, or only those of one
+    /// `(base, quote)` market, refunding their escrow. At most `limit` go per call, repeat until
+    /// the response reports the reset `complete`, which clears the last traded price
+    Reset {
+        market: Option<(String, String)>,
+        limit: Option<u32>,
+    },
+}
+
+/// Where trading fees are sent
+#[cosmwasm_schema::cw_serde]
+pub enum FeeRecipient {
+    /// Keep them in the Abstract account the app is installed on
+    Account,
+    /// Send them to an address, e.g. a treasury
+    Address(String),
+}
+
+/// Side of the book an order trades against: buys take asks and rest as bids, sells the reverse
+#[cosmwasm_schema::cw_serde]
+#[derive(Copy, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Side::Buy => write!(f, "buy"),
+            Side::Sell => write!(f, "sell"),
+        }
+    }
+}
+
+/// How long a limit order stays on the book
+#[cosmwasm_schema::cw_serde]
+#[derive(Copy, Eq, Default)]
+pub enum TimeInForce {
+    /// Rest whatever does not cross until it is filled or cancelled
+    #[default]
+    GoodTillCancelled,
+    /// Fill what crosses right away and refund the rest
+    ImmediateOrCancel,
+    /// Fill the whole order right away or fail
+    FillOrKill,
+}
+
+impl fmt::Display for TimeInForce {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TimeInForce::GoodTillCancelled => write!(f, "gtc"),
+            TimeInForce::ImmediateOrCancel => write!(f, "ioc"),
+            TimeInForce::FillOrKill => write!(f, "fok"),
+        }
+    }
+}
+
+/// What a post-only order does when its price would cross the other side of the book
+#[cosmwasm_schema::cw_serde]
+#[derive(Copy, Eq)]
+pub enum PostOnly {
+    /// Fail the order
+    Reject,
+    /// Rest it one tick behind the best price on the other side instead
+    Reprice,
+}
+
+/// How far the trigger of a trailing stop stays behind the best price
+#[cosmwasm_schema::cw_serde]
+#[derive(Copy)]
+pub enum Trail {
+    /// A fixed price distance, on the market's tick grid
+    Offset(Decimal),
+    /// A share of the best price, `5` for 5%
+    Percent(Decimal),
+}
+
+impl fmt::Display for Trail {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trail::Offset(offset) => write!(f, "{offset}"),
+            Trail::Percent(percent) => write!(f, "{percent}%"),
+        }
+    }
+}
+
+#[cosmwasm_schema::cw_serde]
+#[derive(Copy, Eq)]
+pub enum OrderType {
+    Limit,
+    Market,
+}
+
+impl fmt::Display for OrderType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OrderType::Limit => write!(f, "limit"),
+            OrderType::Market => write!(f, "market"),
+        }
+    }
+}
+
+/// Orders paid for in cw20 tokens, embedded in the `Cw20ReceiveMsg` of a cw20 `Send`
+#[cosmwasm_schema::cw_serde]
+pub enum OrderbookReceiveMsg {
+    /// Credit the tokens to the sender's balance
+    Deposit {},
+    /// Place a limit order
+    LimitOrder {
+        base: String,
+        quote: String,
+        price: Decimal,
+        side: Side,
+        /// Stop matching and refund the order once this height or time is reached
+        expires: Option<Expiration>,
+        /// Never take liquidity, what happens to an order that would cross
+        post_only: Option<PostOnly>,
+        /// Good till cancelled when left out
+        time_in_force: Option<TimeInForce>,
+    },
+    /// Place a market order, filled immediately against the resting book
+    MarketOrder {
+        base: String,
+        quote: String,
+        side: Side,
+    },
+    /// Place a stop order
+    StopOrder {
+        base: String,
+        quote: String,
+        side: Side,
+        trigger_price: Decimal,
+        limit_price: Option<Decimal>,
+    },
+    /// Place a trailing stop
+    TrailingStop {
+        base: String,
+        quote: String,
+        side: Side,
+        trail: Trail,
     },
-    /// Admin method - reset count
-    Reset {},
 }
 
 pub type Route = AccountTrace;
@@ -42,10 +267,10 @@ pub struct OrderbookEvent {
     pub base: String,
     pub quote: String,
     pub price: Decimal,
-    pub side: String,
+    pub side: Side,
     pub quantity: Uint128,
     pub sender: String,
-    pub order_type: String,
+    pub order_type: OrderType,
 }
 
 #[cosmwasm_schema::cw_serde]
@@ -73,21 +298,195 @@ pub struct OrderbookMigrateMsg {}
 pub enum OrderbookQueryMsg {
     #[returns(ConfigResponse)]
     Config {},
+    /// Fees kept in the account and not withdrawn yet, per asset
+    #[returns(ProtocolRevenueResponse)]
+    ProtocolRevenue {},
+    /// Whether the account holds enough of every asset to back the book, balances and fees.
+    /// Walks the whole book, meant for monitoring rather than for contracts to call
+    #[returns(InvariantsResponse)]
+    Invariants {},
+    /// Free balance of an account, funds locked in its orders are not included
+    #[returns(BalancesResponse)]
+    Balances { owner: String },
+    /// Rolling volume of an account and the fee rates it currently pays
+    #[returns(FeeTierResponse)]
+    FeeTier { account: String },
+    /// Listed markets, paginated by `(base, quote)`
+    #[returns(MarketsResponse)]
+    Markets {
+        /// Base and quote of the last market of the previous page
+        start_after: Option<(String, String)>,
+        limit: Option<u32>,
+    },
+    /// Resting bids of a market, best price first
     #[returns(BidsResponse)]
-    Bids {},
+    Bids {
+        base: String,
+        quote: String,
+        /// Price and id of the last bid of the previous page
+        start_after: Option<(Decimal, u64)>,
+        limit: Option<u32>,
+    },
+    /// Resting asks of a market, best price first
     #[returns(AsksResponse)]
-    Asks {},
+    Asks {
+        base: String,
+        quote: String,
+        /// Price and id of the last ask of the previous page
+        start_after: Option<(Decimal, u64)>,
+        limit: Option<u32>,
+    },
+    /// Book of a market aggregated by price level
+    #[returns(DepthResponse)]
+    Depth {
+        base: String,
+        quote: String,
+        /// Number of price levels returned per side
+        levels: u32,
+    },
+    /// Price of the last trade in a market
+    #[returns(LastPriceResponse)]
+    LastPrice { base: String, quote: String },
+    /// Recent trades of a market, newest first
+    #[returns(TradesResponse)]
+    Trades {
+        base: String,
+        quote: String,
+        /// Id of the last trade of the previous page
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Orders an account has resting on the book, oldest first. Expired ones show as such until
+    /// they are pruned
+    #[returns(OrdersResponse)]
+    OpenOrders {
+        owner: String,
+        market: Option<(String, String)>,
+        /// Id of the last order of the previous page
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+    /// Status of a limit order, including ones that left the book
+    #[returns(Order)]
+    Order { order_id: u64 },
+    /// Status of a stop order and its current trigger. A stop-limit that triggered rests as a
+    /// limit order with the same id, a stop-market leaves no order behind
+    #[returns(StopOrder)]
+    Stop { order_id: u64 },
+    /// Stop orders of an account still waiting for their trigger, oldest first
+    #[returns(StopsResponse)]
+    Stops {
+        owner: String,
+        market: Option<(String, String)>,
+        /// Id of the last stop of the previous page
+        start_after: Option<u64>,
+        limit: Option<u32>,
+    },
+}
+
+#[cosmwasm_schema::cw_serde]
+pub struct ConfigResponse {
+    pub maker_fee_bps: u16,
+    pub taker_fee_bps: u16,
+    /// `None` when fees are kept in the Abstract account
+    pub fee_recipient: Option<Addr>,
+    pub fee_tiers: Vec<FeeTier>,
 }
 
 #[cosmwasm_schema::cw_serde]
-pub struct ConfigResponse {}
+pub struct ProtocolRevenueResponse {
+    pub revenue: Vec<AnsAsset>,
+}
+
+/// What the app owes in one asset against what the account holds of it
+#[cosmwasm_schema::cw_serde]
+pub struct AssetBacking {
+    pub asset: AssetEntry,
+    /// Escrow of resting orders
+    pub locked: Uint128,
+    /// Free balances deposited by accounts
+    pub balances: Uint128,
+    /// Fees not withdrawn yet
+    pub fees: Uint128,
+    /// Balance of the account proxy
+    pub held: Uint128,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub struct InvariantsResponse {
+    pub assets: Vec<AssetBacking>,
+    /// Amount missing per asset, empty when everything is backed
+    pub shortfall: Vec<AnsAsset>,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub struct BalancesResponse {
+    pub balances: Vec<AnsAsset>,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub struct FeeTierResponse {
+    /// Quote volume traded over the rolling window
+    pub volume: Uint128,
+    /// Index in the tier table, `None` when the base rates apply
+    pub tier: Option<u32>,
+    pub maker_fee_bps: u16,
+    pub taker_fee_bps: u16,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub struct MarketsResponse {
+    pub markets: Vec<Market>,
+}
 
 #[cosmwasm_schema::cw_serde]
 pub struct BidsResponse {
-    pub bids: Vec<((String, String), Vec<BidAsk>)>,
+    pub bids: Vec<BidAsk>,
 }
 
 #[cosmwasm_schema::cw_serde]
 pub struct AsksResponse {
-    pub asks: Vec<((String, String), Vec<BidAsk>)>,
+    pub asks: Vec<BidAsk>,
+}
+
+/// All resting orders at one price
+#[cosmwasm_schema::cw_serde]
+pub struct DepthLevel {
+    pub price: Decimal,
+    /// Total base quantity that can be filled at this price
+    pub quantity: Uint128,
+    pub orders: u32,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub struct DepthResponse {
+    /// Best bid levels first
+    pub bids: Vec<DepthLevel>,
+    /// Best ask levels first
+    pub asks: Vec<DepthLevel>,
+    pub best_bid: Option<Decimal>,
+    pub best_ask: Option<Decimal>,
+    pub mid: Option<Decimal>,
+    pub spread: Option<Decimal>,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub struct LastPriceResponse {
+    /// `None` until the market has traded
+    pub price: Option<Decimal>,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub struct TradesResponse {
+    pub trades: Vec<Trade>,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub struct OrdersResponse {
+    pub orders: Vec<Order>,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub struct StopsResponse {
+    pub stops: Vec<StopOrder>,
 }
@@ -1,24 +1,379 @@
-use cosmwasm_std::{Addr, Decimal, Uint128};
-use cw_storage_plus::{Item, Map};
+use crate::msg::{Side, Trail};
+
+use abstract_app::objects::AssetEntry;
+use cosmwasm_std::{Addr, Decimal, Order as StorageOrder, StdResult, Storage, Timestamp, Uint128};
+use cw_storage_plus::{Bound, Item, Map};
+use cw_utils::Expiration;
+
+#[cosmwasm_schema::cw_serde]
+pub struct Config {
+    /// Fee charged to the resting side of a fill, in basis points of its quote amount
+    pub maker_fee_bps: u16,
+    /// Fee charged to the incoming side of a fill, in basis points of its quote amount
+    pub taker_fee_bps: u16,
+    /// Where fees are sent, `None` keeps them in the Abstract account
+    pub fee_recipient: Option<Addr>,
+    /// Discounted rates by rolling volume, ascending in `min_volume`
+    pub fee_tiers: Vec<FeeTier>,
+}
+
+impl Config {
+    /// Index of the highest tier `volume` reaches, `None` below the first one
+    pub fn fee_tier(&self, volume: Uint128) -> Option<usize> {
+        self.fee_tiers
+            .iter()
+            .rposition(|tier| volume >= tier.min_volume)
+    }
+
+    /// Maker and taker fee of an account that traded `volume`, the base rates apply below the
+    /// first tier
+    pub fn fee_rates(&self, volume: Uint128) -> (u16, u16) {
+        match self.fee_tier(volume) {
+            Some(index) => (
+                self.fee_tiers[index].maker_fee_bps,
+                self.fee_tiers[index].taker_fee_bps,
+            ),
+            None => (self.maker_fee_bps, self.taker_fee_bps),
+        }
+    }
+}
+
+/// Fee rates for accounts that traded at least `min_volume` of quote over the rolling window
+#[cosmwasm_schema::cw_serde]
+pub struct FeeTier {
+    pub min_volume: Uint128,
+    pub maker_fee_bps: u16,
+    pub taker_fee_bps: u16,
+}
+
+/// Basis points in a whole
+pub const BPS_DENOMINATOR: u16 = 10_000;
+/// Highest maker or taker fee the admin can set
+pub const MAX_FEE_BPS: u16 = 1_000;
 
 #[cosmwasm_schema::cw_serde]
-pub struct Config {}
+pub enum MarketStatus {
+    /// Open for trading
+    Active,
+    /// Listed but not accepting new orders, resting orders can still be cancelled
+    Halted,
+}
+
+/// A listed market and the parameters orders in it have to respect
+#[cosmwasm_schema::cw_serde]
+pub struct Market {
+    pub base: AssetEntry,
+    pub quote: AssetEntry,
+    /// Increment prices have to be a multiple of
+    pub tick_size: Decimal,
+    /// Increment base quantities have to be a multiple of
+    pub lot_size: Uint128,
+    /// Smallest base quantity an order can be placed for
+    pub min_order_size: Uint128,
+    pub status: MarketStatus,
+    pub created_at: Timestamp,
+}
 
 #[cosmwasm_schema::cw_serde]
 pub struct BidAsk {
+    pub id: u64,
     pub account: Addr,
     pub price: Decimal,
     pub quantity: Uint128,
+    /// When the order stops being matched, `Never` for good till cancelled orders
+    pub expires: Expiration,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub enum OrderStatus {
+    Open,
+    PartiallyFilled,
+    Filled,
+    Cancelled,
+    /// Reached its expiry before it was filled, the rest was refunded or is about to be
+    Expired,
+}
+
+impl OrderStatus {
+    /// Whether the order still rests on the book
+    pub fn is_open(&self) -> bool {
+        matches!(self, OrderStatus::Open | OrderStatus::PartiallyFilled)
+    }
+}
+
+/// Where an order's escrow came from, and so where its proceeds and refunds go
+#[cosmwasm_schema::cw_serde]
+#[derive(Copy, Eq)]
+pub enum Funding {
+    /// Sent along with the order, paid out by transfer
+    Transfer,
+    /// Locked from the owner's free balance, paid out to it
+    Balance,
+}
+
+/// A limit order, kept after it leaves the book so its final status can be looked up
+#[cosmwasm_schema::cw_serde]
+pub struct Order {
+    pub id: u64,
+    pub base: String,
+    pub quote: String,
+    pub side: Side,
+    pub owner: Addr,
+    pub price: Decimal,
+    /// Amount escrowed when the order was placed: quote for bids, base for asks
+    pub original_quantity: Uint128,
+    /// Amount still resting on the book, in the same asset as `original_quantity`
+    pub remaining_quantity: Uint128,
+    pub funding: Funding,
+    pub expires: Expiration,
+    pub status: OrderStatus,
+    pub created_at_height: u64,
+    pub created_at: Timestamp,
+}
+
+#[cosmwasm_schema::cw_serde]
+pub enum StopStatus {
+    /// Waiting for the last traded price to reach the trigger
+    Pending,
+    /// Turned into a market or limit order with the same id
+    Triggered,
+    Cancelled,
+}
+
+/// A conditional order kept off the book until the last traded price crosses its trigger
+#[cosmwasm_schema::cw_serde]
+pub struct StopOrder {
+    pub id: u64,
+    pub base: String,
+    pub quote: String,
+    pub side: Side,
+    pub owner: Addr,
+    /// Buy stops fire once the last price rises to it, sell stops once it falls to it
+    pub trigger_price: Decimal,
+    /// Price of the limit order the stop turns into, a market order when `None`
+    pub limit_price: Option<Decimal>,
+    /// Amount escrowed, quote for buys and base for sells
+    pub quantity: Uint128,
+    pub funding: Funding,
+    /// Distance a trailing stop keeps its trigger from `mark`
+    pub trail: Option<Trail>,
+    /// Best price traded since a trailing stop was placed: the high for sells, the low for buys
+    pub mark: Option<Decimal>,
+    pub status: StopStatus,
+    pub created_at: Timestamp,
+}
+
+/// A fill between a resting order and an incoming one
+#[cosmwasm_schema::cw_serde]
+pub struct Trade {
+    /// Sequence of the trade within its market
+    pub id: u64,
+    pub price: Decimal,
+    /// Base quantity exchanged
+    pub quantity: Uint128,
+    pub taker_side: Side,
+    pub maker: Addr,
+    pub taker: Addr,
+    pub block_height: u64,
+    pub time: Timestamp,
 }
 
 pub const CONFIG: Item<Config> = Item::new("config");
-pub const LAST_PRICE: Map<(String, String), Uint128> = Map::new("last_price");
+/// Fees kept in the account per ANS asset, the only funds in the account not backing an order
+pub const PROTOCOL_REVENUE: Map<String, Uint128> = Map::new("protocol_revenue");
+
+/// Free balance of each account per ANS asset, deposited funds not locked in an order
+pub const BALANCES: Map<(Addr, String), Uint128> = Map::new("balances");
+
+/// Add to an account's free balance
+pub fn credit_balance(
+    storage: &mut dyn Storage,
+    account: &Addr,
+    asset: &str,
+    amount: Uint128,
+) -> StdResult<()> {
+    BALANCES.update(
+        storage,
+        (account.clone(), asset.to_string()),
+        |balance| -> StdResult<_> { Ok(balance.unwrap_or_default() + amount) },
+    )?;
+
+    Ok(())
+}
+
+/// Markets listed by the admin, keyed by the `(base, quote)` names orders refer to them by
+pub const MARKETS: Map<(String, String), Market> = Map::new("markets");
+pub const LAST_PRICE: Map<(String, String), Decimal> = Map::new("last_price");
+
+/// Number of trades kept per market, older ones are dropped as new ones come in
+pub const TRADE_HISTORY_SIZE: u64 = 1000;
+pub const NEXT_TRADE_ID: Map<(String, String), u64> = Map::new("next_trade_id");
+pub const TRADES: Map<(String, String, u64), Trade> = Map::new("trades");
 
+// Resting orders keyed by market, price priority and order id, so ranging over a market's
+// prefix in ascending order yields the best price first and, within a level, the oldest order.
 // {
-//    (base_asset: "uosmo", quote_asset: "atom"): [
-//          { account: "addr1", price: 1.0, quantity: 1000 },
-//          { account: "addr2", price: 1.1, quantity: 1000 }
-//    ]
+//    ("uosmo", "atom", (price_key(Buy, 1.1), 2)): { id: 2, account: "addr2", price: 1.1, quantity: 1000 },
+//    ("uosmo", "atom", (price_key(Buy, 1.0), 1)): { id: 1, account: "addr1", price: 1.0, quantity: 1000 }
 // }
-pub const BIDS: Map<(String, String), Vec<BidAsk>> = Map::new("bids");
-pub const ASKS: Map<(String, String), Vec<BidAsk>> = Map::new("asks");
+pub type BookKey = (String, String, (u128, u64));
+pub const BIDS: Map<BookKey, BidAsk> = Map::new("bids");
+pub const ASKS: Map<BookKey, BidAsk> = Map::new("asks");
+
+/// Sort key of a price within its side of the book: asks ascend in price, bids descend
+pub fn price_key(side: Side, price: Decimal) -> u128 {
+    match side {
+        Side::Buy => u128::MAX - price.atomics().u128(),
+        Side::Sell => price.atomics().u128(),
+    }
+}
+
+/// Number of days of volume counted towards an account's fee tier
+pub const VOLUME_WINDOW_DAYS: u64 = 30;
+const SECONDS_PER_DAY: u64 = 86_400;
+/// Quote volume each account traded per day since the epoch. Volume is summed across markets in
+/// raw quote units, so tiers only make sense when markets share a quote asset.
+pub const DAILY_VOLUME: Map<(Addr, u64), Uint128> = Map::new("daily_volume");
+
+/// First day still inside the rolling volume window
+fn window_start(now: Timestamp) -> u64 {
+    (now.seconds() / SECONDS_PER_DAY + 1).saturating_sub(VOLUME_WINDOW_DAYS)
+}
+
+/// Quote volume an account traded over the last `VOLUME_WINDOW_DAYS` days, today included
+pub fn rolling_volume(storage: &dyn Storage, account: &Addr, now: Timestamp) -> StdResult<Uint128> {
+    DAILY_VOLUME
+        .prefix(account.clone())
+        .range(
+            storage,
+            Some(Bound::inclusive(window_start(now))),
+            None,
+            StorageOrder::Ascending,
+        )
+        .try_fold(Uint128::zero(), |total, item| Ok(total + item?.1))
+}
+
+/// Add to an account's volume for today and forget the days that left the window
+pub fn add_volume(
+    storage: &mut dyn Storage,
+    account: &Addr,
+    now: Timestamp,
+    amount: Uint128,
+) -> StdResult<()> {
+    let expired = DAILY_VOLUME
+        .prefix(account.clone())
+        .keys(
+            storage,
+            None,
+            Some(Bound::exclusive(window_start(now))),
+            StorageOrder::Ascending,
+        )
+        .collect::<StdResult<Vec<_>>>()?;
+    for day in expired {
+        DAILY_VOLUME.remove(storage, (account.clone(), day));
+    }
+
+    DAILY_VOLUME.update(
+        storage,
+        (account.clone(), now.seconds() / SECONDS_PER_DAY),
+        |volume| -> StdResult<_> { Ok(volume.unwrap_or_default() + amount) },
+    )?;
+
+    Ok(())
+}
+
+pub const NEXT_ORDER_ID: Item<u64> = Item::new("next_order_id");
+pub const ORDERS: Map<u64, Order> = Map::new("orders");
+/// Ids of the orders each account has resting on the book, with their `(base, quote)` market
+pub const OPEN_ORDERS: Map<(Addr, u64), (String, String)> = Map::new("open_orders");
+
+pub const STOP_ORDERS: Map<u64, StopOrder> = Map::new("stop_orders");
+/// Ids of each account's pending stops, with their `(base, quote)` market
+pub const OPEN_STOPS: Map<(Addr, u64), (String, String)> = Map::new("open_stops");
+// Pending stops of a market by trigger price, keyed like the book so the first entry of a market
+// is the next to fire: buy stops lowest trigger first, sell stops highest trigger first
+pub const STOP_BUYS: Map<BookKey, Decimal> = Map::new("stop_buys");
+pub const STOP_SELLS: Map<BookKey, Decimal> = Map::new("stop_sells");
+
+/// Index of the pending stops on `side` and the key of one with `trigger_price`
+pub fn stop_key(
+    side: Side,
+    market: (String, String),
+    trigger_price: Decimal,
+    order_id: u64,
+) -> (Map<'static, BookKey, Decimal>, BookKey) {
+    // a buy stop fires on a rising price, so it sorts like an ask
+    let (index, priority) = match side {
+        Side::Buy => (STOP_BUYS, price_key(Side::Sell, trigger_price)),
+        Side::Sell => (STOP_SELLS, price_key(Side::Buy, trigger_price)),
+    };
+    (index, (market.0, market.1, (priority, order_id)))
+}
+
+// Pending trailing stops of a market by their mark, the first entry of a market is the mark
+// furthest behind: sells lowest high first, buys highest low first
+pub const TRAILING_SELLS: Map<BookKey, Decimal> = Map::new("trailing_sells");
+pub const TRAILING_BUYS: Map<BookKey, Decimal> = Map::new("trailing_buys");
+
+/// Mark index of the trailing stops on `side` and the key of one at `mark`
+pub fn trail_key(
+    side: Side,
+    market: (String, String),
+    mark: Decimal,
+    order_id: u64,
+) -> (Map<'static, BookKey, Decimal>, BookKey) {
+    // a new high moves the marks of sells up, so they sort like asks to find the ones below it
+    let (index, priority) = match side {
+        Side::Buy => (TRAILING_BUYS, price_key(Side::Buy, mark)),
+        Side::Sell => (TRAILING_SELLS, price_key(Side::Sell, mark)),
+    };
+    (index, (market.0, market.1, (priority, order_id)))
+}
+
+// Resting orders that expire, by market and the height or time in nanoseconds they expire at,
+// so the expired ones can be found without walking the book
+pub type ExpiryKey = (String, String, (u64, u64));
+pub const EXPIRE_AT_HEIGHT: Map<ExpiryKey, Side> = Map::new("expire_at_height");
+pub const EXPIRE_AT_TIME: Map<ExpiryKey, Side> = Map::new("expire_at_time");
+
+/// Index of `expires` and the point in it the order expires at, `None` if it never does
+pub fn expiry_index(expires: &Expiration) -> Option<(Map<'static, ExpiryKey, Side>, u64)> {
+    match expires {
+        Expiration::AtHeight(height) => Some((EXPIRE_AT_HEIGHT, *height)),
+        Expiration::AtTime(time) => Some((EXPIRE_AT_TIME, time.nanos())),
+        Expiration::Never {} => None,
+    }
+}
+
+/// Index an order resting on `side` of a market until `expires`
+pub fn track_expiry(
+    storage: &mut dyn Storage,
+    market: (String, String),
+    order_id: u64,
+    side: Side,
+    expires: &Expiration,
+) -> StdResult<()> {
+    match expiry_index(expires) {
+        Some((index, at)) => index.save(storage, (market.0, market.1, (at, order_id)), &side),
+        None => Ok(()),
+    }
+}
+
+/// Drop an order that left the book from the expiry index
+pub fn untrack_expiry(
+    storage: &mut dyn Storage,
+    market: (String, String),
+    order_id: u64,
+    expires: &Expiration,
+) {
+    if let Some((index, at)) = expiry_index(expires) {
+        index.remove(storage, (market.0, market.1, (at, order_id)));
+    }
+}
+
+/// Reserve the next order id, ids start at 1 and only ever increase
+pub fn next_order_id(storage: &mut dyn Storage) -> StdResult<u64> {
+    let id = NEXT_ORDER_ID.may_load(storage)?.unwrap_or(1);
+    NEXT_ORDER_ID.save(storage, &(id + 1))?;
+    Ok(id)
+}
@@ -1,20 +1,40 @@
 use abstract_interface::ExecuteMsgFns;
 
+use cw20::Cw20Coin;
 use cw_asset::AssetInfoBase;
+use cw_plus_interface::cw20_base::{Cw20Base, InstantiateMsg as Cw20InstantiateMsg};
 use orderbook::{
-    contract::interface::OrderbookInterface, msg::OrderbookInstantiateMsg, ORDERBOOK_NAMESPACE,
+    contract::interface::OrderbookInterface,
+    msg::{
+        InvariantsResponse, OrderbookExecuteMsgFns, OrderbookInstantiateMsg, OrderbookQueryMsgFns,
+    },
+    ORDERBOOK_NAMESPACE,
 };
 
 use abstract_app::objects::namespace::Namespace;
 use abstract_client::{AbstractClient, Application, Environment};
-use cosmwasm_std::coins;
+use cosmwasm_std::{coins, Decimal, Uint128};
 
 // Use prelude to get all the necessary imports
 use cw_orch::{anyhow, prelude::*};
 
+/// IBC denom of atom on the mock chain, registered in ANS as `cosmoshub>atom`
+pub const IBC_ATOM: &str = "ibc/27394FB092D2ECCD56123C74F36E4C1F926001CEADA9CA97EA622B25F41E5EB2";
+
+/// Markets listed in the test environment, as `(base, quote)`
+pub const MARKETS: [(&str, &str); 5] = [
+    ("uosmo", "atom"),
+    ("ntrn", "juno"),
+    ("uosmo", "cosmoshub>atom"),
+    ("test", "atom"),
+    ("uosmo", "test"),
+];
+
 pub struct TestEnv<Env: CwEnv> {
     pub abs: AbstractClient<Env>,
     pub app: Application<Env, OrderbookInterface<Env>>,
+    /// cw20 token registered in ANS as `test`, the sender holds 1000 of it
+    pub cw20: Cw20Base<Env>,
 }
 
 impl TestEnv<MockBech32> {
@@ -33,12 +53,34 @@ impl TestEnv<MockBech32> {
         abs_client.add_balance(sender.clone(), &coins(1000, "uosmo"))?;
         abs_client.add_balance(sender.clone(), &coins(1000, "ntrn"))?;
         abs_client.add_balance(sender.clone(), &coins(1000, "juno"))?;
+        abs_client.add_balance(sender.clone(), &coins(1000, IBC_ATOM))?;
+
+        // create a cw20 token owned by the sender
+        let cw20 = Cw20Base::new("cw20", mock.clone());
+        cw20.upload()?;
+        cw20.instantiate(
+            &Cw20InstantiateMsg {
+                name: "Test".to_string(),
+                symbol: "TEST".to_string(),
+                decimals: 6,
+                initial_balances: vec![Cw20Coin {
+                    address: sender.to_string(),
+                    amount: 1000u128.into(),
+                }],
+                mint: None,
+                marketing: None,
+            },
+            None,
+            None,
+        )?;
 
         // register tokens with ANS
         let atom = AssetInfoBase::native("atom");
         let uosmo = AssetInfoBase::native("uosmo");
         let ntrn = AssetInfoBase::native("ntrn");
         let juno = AssetInfoBase::native("juno");
+        let ibc_atom = AssetInfoBase::native(IBC_ATOM);
+        let test = AssetInfoBase::cw20(cw20.address()?);
 
         let ans = abs_client.name_service();
         ans.update_asset_addresses(
@@ -47,34 +89,12 @@ impl TestEnv<MockBech32> {
                 ("uosmo".into(), uosmo.clone()),
                 ("ntrn".into(), ntrn.clone()),
                 ("juno".into(), juno.clone()),
+                ("cosmoshub>atom".into(), ibc_atom.clone()),
+                ("test".into(), test.clone()),
             ],
             vec![],
         )?;
 
-        // mint some cw20 tokens to the sender
-        // let cw20 = Cw20Base::new("cw20", mock.clone());
-        // cw20.upload()?;
-        // let resp = cw20.instantiate(
-        //     &cw20_base::InstantiateMsg {
-        //         name: "Test".to_string(),
-        //         symbol: "TEST".to_string(),
-        //         decimals: 6,
-        //         initial_balances: vec![],
-        //         mint: Some(MinterResponse {
-        //             minter: sender.to_string(),
-        //             cap: None,
-        //         }),
-        //         marketing: None,
-        //     },
-        //     None,
-        //     None,
-        // )?;
-        // let cw20_address = resp.event_attr_value("instantiate", "_contract_address")?;
-        // println!("cw20 address: {:#?}", cw20_address);
-        // cw20.mint(1_000_000_u128, sender.to_string())?;
-        // let balance = cw20.balance(sender.clone())?;
-        // println!("balance: {:#?}", balance);
-
         // get native balance
         let native_balance = abs_client.environment().query_all_balances(&sender)?;
         assert!(native_balance.iter().any(|coin| coin.denom == "atom"));
@@ -90,9 +110,35 @@ impl TestEnv<MockBech32> {
             .account()
             .install_app::<OrderbookInterface<_>>(&OrderbookInstantiateMsg {}, &[])?;
 
+        // list the markets with parameters loose enough for any order the tests place
+        for (base, quote) in MARKETS {
+            app.create_market(
+                base,
+                Uint128::one(),
+                Uint128::one(),
+                quote,
+                Decimal::percent(1),
+            )?;
+        }
+
         Ok(TestEnv {
             abs: abs_client,
             app,
+            cw20,
         })
     }
 }
+
+/// Fail unless the account holds enough of every asset to back resting orders, balances and fees
+pub fn assert_backed<Env: CwEnv>(
+    app: &Application<Env, OrderbookInterface<Env>>,
+) -> anyhow::Result<()> {
+    let invariants: InvariantsResponse = app.invariants()?;
+    assert!(
+        invariants.shortfall.is_empty(),
+        "account is short of {:?}",
+        invariants.shortfall
+    );
+
+    Ok(())
+}
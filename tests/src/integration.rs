@@ -1,15 +1,22 @@
+use std::str::FromStr;
+
 use orderbook::{
-    msg::{BidsResponse, ConfigResponse, OrderbookExecuteMsgFns, OrderbookQueryMsgFns},
+    msg::{
+        AsksResponse, BidsResponse, ConfigResponse, FeeRecipient, LastPriceResponse,
+        MarketsResponse, OrderbookExecuteMsgFns, OrderbookQueryMsgFns, Side,
+    },
+    state::MarketStatus,
     OrderbookError,
 };
 
+use abstract_app::objects::AssetEntry;
 use abstract_client::Environment;
-use cosmwasm_std::coins;
+use cosmwasm_std::{coins, Decimal, Uint128};
 use cw_controllers::AdminError;
 // Use prelude to get all the necessary imports
 use cw_orch::{anyhow, prelude::*};
 
-use super::common::TestEnv;
+use super::common::{assert_backed, TestEnv, MARKETS};
 
 #[test]
 fn successful_install() -> anyhow::Result<()> {
@@ -17,7 +24,15 @@ fn successful_install() -> anyhow::Result<()> {
     let app = env.app;
 
     let config = app.config()?;
-    assert_eq!(config, ConfigResponse {});
+    assert_eq!(
+        config,
+        ConfigResponse {
+            maker_fee_bps: 0,
+            taker_fee_bps: 0,
+            fee_recipient: None,
+            fee_tiers: vec![],
+        }
+    );
     Ok(())
 }
 
@@ -26,9 +41,100 @@ fn successful_reset() -> anyhow::Result<()> {
     let env = TestEnv::setup()?;
     let app = env.app;
 
-    app.reset()?;
-    let bids: BidsResponse = app.bids()?;
+    let abs = env.abs;
+    let sender = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    // an empty book resets to itself
+    app.reset(None, None)?;
+    let bids: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(bids.bids.len(), 0);
+
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(50, "atom"),
+    )?;
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(20, "uosmo"),
+    )?;
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(4, "atom"),
+    )?;
+    app.limit_order(
+        "ntrn".to_string(),
+        Decimal::one(),
+        "juno".to_string(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "juno"),
+    )?;
+
+    // only the scoped market is cleared, a bit at a time, each order is refunded what it had left
+    let scope = Some((osmo_asset.clone(), atom_asset.clone()));
+    let resp = app.reset(Some(1), scope.clone())?;
+    assert_eq!(resp.event_attr_value("wasm", "cancelled")?, "1");
+    assert_eq!(resp.event_attr_value("wasm", "complete")?, "false");
+    let last_price: LastPriceResponse = app.last_price(osmo_asset.clone(), atom_asset.clone())?;
+    assert!(last_price.price.is_some());
+
+    let resp = app.reset(None, scope)?;
+    assert_eq!(resp.event_attr_value("wasm", "cancelled")?, "1");
+    assert_eq!(resp.event_attr_value("wasm", "complete")?, "true");
+    let refunds = resp
+        .events
+        .iter()
+        .filter(|event| event.ty == "wasm-refund")
+        .count();
+    assert_eq!(refunds, 1);
+
+    let bids: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(bids.bids.len(), 0);
+    let asks: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks.asks.len(), 0);
+    let last_price: LastPriceResponse = app.last_price(osmo_asset.clone(), atom_asset.clone())?;
+    assert_eq!(last_price.price, None);
+    let balance = abs.environment().balance(&sender, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1004));
+    let balance = abs.environment().balance(&sender, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(998));
+
+    let bids: BidsResponse = app.bids("ntrn".to_string(), "juno".to_string(), None, None)?;
+    assert_eq!(bids.bids.len(), 1);
+
+    app.reset(None, None)?;
+    let bids: BidsResponse = app.bids("ntrn".to_string(), "juno".to_string(), None, None)?;
     assert_eq!(bids.bids.len(), 0);
+    let balance = abs.environment().balance(&sender, Some("juno".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1000));
+
+    assert_backed(&app)?;
+
     Ok(())
 }
 
@@ -39,7 +145,7 @@ fn failed_reset() -> anyhow::Result<()> {
 
     let err: OrderbookError = app
         .call_as(&Addr::unchecked("NotAdmin"))
-        .reset()
+        .reset(None, None)
         .unwrap_err()
         .downcast()
         .unwrap();
@@ -52,10 +158,51 @@ fn update_config() -> anyhow::Result<()> {
     let env = TestEnv::setup()?;
     let app = env.app;
 
-    app.update_config()?;
+    let abs = env.abs;
+    let treasury = abs.environment().addr_make("treasury");
+
+    app.update_config(
+        Some(FeeRecipient::Address(treasury.to_string())),
+        None,
+        Some(10),
+        Some(20),
+    )?;
     let config = app.config()?;
-    let expected_response = orderbook::msg::ConfigResponse {};
+    let expected_response = orderbook::msg::ConfigResponse {
+        maker_fee_bps: 10,
+        taker_fee_bps: 20,
+        fee_recipient: Some(treasury),
+        fee_tiers: vec![],
+    };
     assert_eq!(config, expected_response);
+
+    // fields left out are kept
+    app.update_config(Some(FeeRecipient::Account), None, None, Some(5))?;
+    let config = app.config()?;
+    assert_eq!(config.maker_fee_bps, 10);
+    assert_eq!(config.taker_fee_bps, 5);
+    assert_eq!(config.fee_recipient, None);
+
+    let err: OrderbookError = app
+        .update_config(None, None, Some(1001), None)
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::FeeTooHigh {
+            fee_bps: 1001,
+            max_bps: 1000
+        }
+    );
+
+    let err: OrderbookError = app
+        .call_as(&abs.environment().addr_make("not_admin"))
+        .update_config(None, None, Some(1), None)
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::Admin(AdminError::NotAdmin {}));
     Ok(())
 }
 
@@ -79,3 +226,252 @@ fn balance_added() -> anyhow::Result<()> {
     assert_eq!(balances, add_balance);
     Ok(())
 }
+
+#[test]
+fn create_markets() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+
+    let markets: MarketsResponse = app.markets(None, None)?;
+    assert_eq!(markets.markets.len(), MARKETS.len());
+
+    // orders can only be placed in listed markets
+    let err: OrderbookError = app
+        .limit_order(
+            "atom".to_string(),
+            Decimal::one(),
+            "uosmo".to_string(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(10, "atom"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::MarketNotFound {
+            base: "atom".to_string(),
+            quote: "uosmo".to_string()
+        }
+    );
+
+    // only the admin lists markets
+    let err: OrderbookError = app
+        .call_as(&abs.environment().addr_make("not_admin"))
+        .create_market(
+            "atom",
+            Uint128::one(),
+            Uint128::one(),
+            "uosmo",
+            Decimal::percent(1),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::Admin(AdminError::NotAdmin {}));
+
+    app.create_market(
+        "atom",
+        Uint128::new(10),
+        Uint128::new(100),
+        "uosmo",
+        Decimal::percent(5),
+    )?;
+
+    let err: OrderbookError = app
+        .create_market(
+            "atom",
+            Uint128::one(),
+            Uint128::one(),
+            "uosmo",
+            Decimal::percent(1),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::MarketExists {
+            base: "atom".to_string(),
+            quote: "uosmo".to_string()
+        }
+    );
+
+    let err: OrderbookError = app
+        .create_market(
+            "atom",
+            Uint128::zero(),
+            Uint128::one(),
+            "juno",
+            Decimal::percent(1),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::InvalidMarket("lot size must be greater than zero".to_string())
+    );
+
+    // assets have to be registered in ANS
+    assert!(app
+        .create_market(
+            "atom",
+            Uint128::one(),
+            Uint128::one(),
+            "unknown",
+            Decimal::percent(1),
+        )
+        .is_err());
+
+    let page: MarketsResponse = app.markets(Some(2), None)?;
+    assert_eq!(page.markets.len(), 2);
+    let last = page.markets.last().unwrap();
+    let page: MarketsResponse =
+        app.markets(None, Some((last.base.to_string(), last.quote.to_string())))?;
+    // the setup markets plus atom/uosmo, minus the first page
+    assert_eq!(page.markets.len(), MARKETS.len() + 1 - 2);
+
+    let market = app
+        .markets(None, None)?
+        .markets
+        .into_iter()
+        .find(|market| market.base == AssetEntry::new("atom"))
+        .unwrap();
+    assert_eq!(market.tick_size, Decimal::percent(5));
+    assert_eq!(market.lot_size, Uint128::new(10));
+    assert_eq!(market.min_order_size, Uint128::new(100));
+    assert_eq!(market.status, MarketStatus::Active);
+    assert_eq!(market.created_at, abs.environment().block_info()?.time);
+
+    Ok(())
+}
+
+#[test]
+fn halted_markets() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let sender = abs.environment().sender_addr();
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    let resp = app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "uosmo"),
+    )?;
+    let order_id: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+
+    let err: OrderbookError = app
+        .call_as(&Addr::unchecked("NotAdmin"))
+        .update_market(
+            osmo_asset.clone(),
+            atom_asset.clone(),
+            None,
+            Some(MarketStatus::Halted),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::Admin(AdminError::NotAdmin {}));
+
+    let err: OrderbookError = app
+        .update_market("juno", "unknown", None, Some(MarketStatus::Halted))
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::MarketNotFound {
+            base: "juno".to_string(),
+            quote: "unknown".to_string(),
+        }
+    );
+
+    // a halted market takes no new orders, resting ones can still leave
+    app.update_market(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        None,
+        Some(MarketStatus::Halted),
+    )?;
+    let err: OrderbookError = app
+        .market_order(
+            osmo_asset.clone(),
+            atom_asset.clone(),
+            Side::Buy,
+            None,
+            &coins(10, "atom"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::MarketNotActive {
+            base: osmo_asset.clone(),
+            quote: atom_asset.clone(),
+        }
+    );
+
+    app.cancel_order(order_id)?;
+    let balance = abs.environment().balance(&sender, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1000));
+
+    // resuming it with a higher minimum applies to new orders
+    app.update_market(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Some(Uint128::new(20)),
+        Some(MarketStatus::Active),
+    )?;
+    let err: OrderbookError = app
+        .limit_order(
+            osmo_asset.clone(),
+            Decimal::one(),
+            atom_asset.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(10, "uosmo"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::OrderTooSmall {
+            quantity: Uint128::new(10),
+            min_order_size: Uint128::new(20),
+        }
+    );
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(20, "uosmo"),
+    )?;
+    assert_backed(&app)?;
+
+    Ok(())
+}
@@ -6,3 +6,6 @@ mod integration;
 
 #[cfg(test)]
 mod order;
+
+#[cfg(test)]
+mod query;
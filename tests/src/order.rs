@@ -1,18 +1,27 @@
 use std::str::FromStr;
 
 use orderbook::{
-    msg::{AsksResponse, BidsResponse, OrderbookExecuteMsgFns, OrderbookQueryMsgFns},
-    state::BidAsk,
+    msg::{
+        AsksResponse, BalancesResponse, BidsResponse, FeeRecipient, FeeTierResponse,
+        OrderbookExecuteMsg, OrderbookExecuteMsgFns, OrderbookQueryMsgFns, OrderbookReceiveMsg,
+        OrdersResponse, PostOnly, ProtocolRevenueResponse, Side, StopsResponse, TimeInForce, Trail,
+    },
+    state::{BidAsk, FeeTier, OrderStatus, StopStatus},
     OrderbookError,
 };
 
+use abstract_app::objects::AnsAsset;
 use abstract_client::Environment;
-use cosmwasm_std::{coins, Decimal, Uint128};
+use cosmwasm_std::{coins, from_json, to_json_binary, Decimal, Uint128};
+use cw20::msg::Cw20ExecuteMsgFns;
+use cw20_base::msg::QueryMsgFns as _;
+use cw_controllers::AdminError;
+use cw_utils::{Expiration, PaymentError};
 
 // Use prelude to get all the necessary imports
 use cw_orch::{anyhow, prelude::*};
 
-use super::common::TestEnv;
+use super::common::{assert_backed, TestEnv, IBC_ATOM};
 
 #[test]
 fn place_limit_order() -> anyhow::Result<()> {
@@ -47,7 +56,11 @@ fn place_limit_order() -> anyhow::Result<()> {
             osmo_asset.clone(),
             Decimal::zero(),
             atom_asset.clone(),
-            "buy",
+            Side::Buy,
+            None,
+            None,
+            None,
+            None,
             &atom_coins,
         )
         .unwrap_err()
@@ -61,7 +74,11 @@ fn place_limit_order() -> anyhow::Result<()> {
             osmo_asset.clone(),
             Decimal::one(),
             atom_asset.clone(),
-            "buy",
+            Side::Buy,
+            None,
+            None,
+            None,
+            None,
             &coins(0, "atom"),
         )
         .unwrap_err()
@@ -72,19 +89,11 @@ fn place_limit_order() -> anyhow::Result<()> {
         "Cannot transfer empty coins amount".to_string()
     );
 
-    // make sure invalid side doesn't work
-    let err: OrderbookError = app
-        .limit_order(
-            osmo_asset.clone(),
-            Decimal::one(),
-            atom_asset.clone(),
-            "invalid",
-            &atom_coins,
-        )
-        .unwrap_err()
-        .downcast()
-        .unwrap();
-    assert_eq!(err, OrderbookError::InvalidSide("invalid".to_string()));
+    // make sure invalid side doesn't even deserialize
+    let msg = from_json::<OrderbookExecuteMsg>(
+        r#"{"limit_order":{"base":"uosmo","quote":"atom","price":"1","side":"invalid"}}"#,
+    );
+    assert!(msg.is_err());
 
     // make sure the deposited asset matches the asset expected by the side of the order
     let err: OrderbookError = app
@@ -92,7 +101,11 @@ fn place_limit_order() -> anyhow::Result<()> {
             osmo_asset.clone(),
             Decimal::one(),
             atom_asset.clone(),
-            "sell",
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
             &atom_coins,
         )
         .unwrap_err()
@@ -101,13 +114,18 @@ fn place_limit_order() -> anyhow::Result<()> {
     assert_eq!(err, OrderbookError::IncorrectAsset);
 
     // make sure bids work
-    let _ = app.limit_order(
+    let resp = app.limit_order(
         osmo_asset.clone(),
         Decimal::one(),
         atom_asset.clone(),
-        "buy",
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
         &atom_coins,
     )?;
+    assert_eq!(resp.event_attr_value("wasm", "order_id")?, "1");
 
     // make sure the atom asset (quote) is reserved from the sender for buy orders
     let balances = abs.environment().balance(&sender, Some("atom".into()))?;
@@ -122,30 +140,34 @@ fn place_limit_order() -> anyhow::Result<()> {
         &atom_coins,
     );
 
-    let bids_resp: BidsResponse = app.bids()?;
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
     println!("bids {:#?}", bids_resp);
 
     assert_eq!(bids_resp.bids.len(), 1);
     assert_eq!(
         bids_resp.bids[0],
-        (
-            (osmo_asset.clone(), atom_asset.clone(),),
-            vec![BidAsk {
-                account: sender.clone(),
-                price: Decimal::one(),
-                quantity: Uint128::one(),
-            }]
-        )
+        BidAsk {
+            id: 1,
+            account: sender.clone(),
+            price: Decimal::one(),
+            quantity: Uint128::one(),
+            expires: Expiration::Never {},
+        }
     );
 
-    // make sure asks work
-    let _ = app.limit_order(
+    // make sure asks work, priced above the bid so the book isn't crossed
+    let resp = app.limit_order(
         osmo_asset.clone(),
-        Decimal::one(),
+        Decimal::from_str("2.0")?,
         atom_asset.clone(),
-        "sell",
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
         &osmo_coins,
     )?;
+    assert_eq!(resp.event_attr_value("wasm", "order_id")?, "2");
 
     // make sure the osmo asset (base) is reserved from the sender for sell orders
     let balances = abs.environment().balance(&sender, Some("uosmo".into()))?;
@@ -159,117 +181,2166 @@ fn place_limit_order() -> anyhow::Result<()> {
         &coins(1, "uosmo"),
     );
 
-    let asks_resp: AsksResponse = app.asks()?;
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
     println!("asks {:#?}", asks_resp);
 
     assert_eq!(asks_resp.asks.len(), 1);
     assert_eq!(
         asks_resp.asks[0],
-        (
-            (osmo_asset.clone(), atom_asset.clone(),),
-            vec![BidAsk {
-                account: sender.clone(),
-                price: Decimal::one(),
-                quantity: Uint128::one(),
-            }]
-        )
+        BidAsk {
+            id: 2,
+            account: sender.clone(),
+            price: Decimal::from_str("2.0")?,
+            quantity: Uint128::one(),
+            expires: Expiration::Never {},
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn limit_orders_cross() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let maker = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(100, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    // two asks at the same price and a worse one behind them
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "uosmo"),
+    )?;
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(5, "uosmo"),
+    )?;
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("4.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "uosmo"),
+    )?;
+
+    // a buy for 13 uosmo at up to 3.0 fills the first ask fully and the second partially
+    app.call_as(&taker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("3.0")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(26, "atom"),
+    )?;
+
+    // the taker received 13 uosmo at the makers' price
+    assert_eq!(
+        abs.environment().balance(&taker, Some("uosmo".into()))?,
+        coins(13, "uosmo")
+    );
+    assert_eq!(
+        abs.environment().balance(&maker, Some("atom".into()))?,
+        coins(1026, "atom")
+    );
+
+    // the partially filled ask keeps its place ahead of the worse level
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(
+        asks_resp.asks,
+        vec![
+            BidAsk {
+                id: 2,
+                account: maker.clone(),
+                price: Decimal::from_str("2.0")?,
+                quantity: Uint128::new(2),
+                expires: Expiration::Never {},
+            },
+            BidAsk {
+                id: 3,
+                account: maker.clone(),
+                price: Decimal::from_str("4.0")?,
+                quantity: Uint128::new(10),
+                expires: Expiration::Never {},
+            },
+        ]
+    );
+
+    // a buy that exhausts every level within its limit rests the unspent remainder
+    app.call_as(&taker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("3.0")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "atom"),
+    )?;
+    assert_eq!(
+        abs.environment().balance(&taker, Some("uosmo".into()))?,
+        coins(15, "uosmo")
+    );
+    assert_eq!(
+        abs.environment().balance(&taker, Some("atom".into()))?,
+        coins(64, "atom")
     );
 
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(
+        bids_resp.bids,
+        vec![BidAsk {
+            id: 5,
+            account: taker.clone(),
+            price: Decimal::from_str("3.0")?,
+            quantity: Uint128::new(6),
+            expires: Expiration::Never {},
+        }]
+    );
+
+    // a sell below the resting bid fills against it at the bid's price
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(2, "uosmo"),
+    )?;
+    assert_eq!(
+        abs.environment().balance(&taker, Some("uosmo".into()))?,
+        coins(17, "uosmo")
+    );
+    assert_eq!(
+        abs.environment().balance(&maker, Some("atom".into()))?,
+        coins(1036, "atom")
+    );
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(bids_resp.bids.len(), 0);
+
+    assert_backed(&app)?;
+
     Ok(())
 }
 
 #[test]
-#[ignore]
 fn place_market_order() -> anyhow::Result<()> {
     let env = TestEnv::setup()?;
     let app = env.app;
-    let _sender = env.abs.environment().sender_addr();
+    let abs = env.abs;
+    let maker = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(100, "atom"))?;
+    abs.add_balance(&taker, &coins(100, "uosmo"))?;
 
     let osmo_asset = "uosmo".to_string();
     let atom_asset = "atom".to_string();
-    let _ntrn_asset = "ntrn".to_string();
-    let _juno_asset = "juno".to_string();
 
-    let atom_coins = coins(1, "atom");
+    // make sure an empty book doesn't work
+    let err: OrderbookError = app
+        .call_as(&taker)
+        .market_order(
+            osmo_asset.clone(),
+            atom_asset.clone(),
+            Side::Buy,
+            None,
+            &coins(10, "atom"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::EmptyBook {
+            base: osmo_asset.clone(),
+            quote: atom_asset.clone(),
+        }
+    );
 
     // add some limit orders
     app.limit_order(
         osmo_asset.clone(),
         Decimal::from_str("3.0")?,
         atom_asset.clone(),
-        "sell",
-        &atom_coins,
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "uosmo"),
     )?;
     app.limit_order(
         osmo_asset.clone(),
         Decimal::from_str("4.0")?,
         atom_asset.clone(),
-        "sell",
-        &atom_coins,
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "uosmo"),
     )?;
     app.limit_order(
         osmo_asset.clone(),
         Decimal::from_str("2.0")?,
         atom_asset.clone(),
-        "buy",
-        &atom_coins,
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(20, "atom"),
     )?;
     app.limit_order(
         osmo_asset.clone(),
         Decimal::from_str("1.0")?,
         atom_asset.clone(),
-        "buy",
-        &atom_coins,
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "atom"),
     )?;
 
-    // make sure 0 quantity doesn't work
+    // make sure invalid side doesn't even deserialize
+    let msg = from_json::<OrderbookExecuteMsg>(
+        r#"{"market_order":{"base":"uosmo","quote":"atom","side":"invalid"}}"#,
+    );
+    assert!(msg.is_err());
+
+    // make sure the deposited asset matches the side of the order
     let err: OrderbookError = app
+        .call_as(&taker)
         .market_order(
             osmo_asset.clone(),
             atom_asset.clone(),
-            "buy",
-            &coins(0, "atom"),
+            Side::Buy,
+            None,
+            &coins(10, "uosmo"),
         )
         .unwrap_err()
         .downcast()
         .unwrap();
-    assert_eq!(err, OrderbookError::ZeroQuantity);
+    assert_eq!(err, OrderbookError::IncorrectAsset);
+
+    // a buy sweeps the asks from the best price outward: 10 @ 3.0 then 5 @ 4.0
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(50, "atom"),
+    )?;
+    assert_eq!(
+        abs.environment().balance(&taker, Some("uosmo".into()))?,
+        coins(115, "uosmo")
+    );
+    assert_eq!(
+        abs.environment().balance(&taker, Some("atom".into()))?,
+        coins(50, "atom")
+    );
+    assert_eq!(
+        abs.environment().balance(&maker, Some("atom".into()))?,
+        coins(1000 - 30 + 50, "atom")
+    );
+
+    // whatever the book can't absorb is refunded
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(45, "atom"),
+    )?;
+    assert_eq!(
+        abs.environment().balance(&taker, Some("uosmo".into()))?,
+        coins(120, "uosmo")
+    );
+    assert_eq!(
+        abs.environment().balance(&taker, Some("atom".into()))?,
+        coins(30, "atom")
+    );
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks.len(), 0);
+
+    // a sell sweeps the bids: 10 @ 2.0 then 5 @ 1.0
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        &coins(15, "uosmo"),
+    )?;
+    assert_eq!(
+        abs.environment().balance(&taker, Some("atom".into()))?,
+        coins(55, "atom")
+    );
+    assert_eq!(
+        abs.environment().balance(&maker, Some("uosmo".into()))?,
+        coins(1000 - 20 + 15, "uosmo")
+    );
 
-    // make sure invalid side doesn't work
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(
+        bids_resp.bids,
+        vec![BidAsk {
+            id: 4,
+            account: maker.clone(),
+            price: Decimal::one(),
+            quantity: Uint128::new(5),
+            expires: Expiration::Never {},
+        }]
+    );
+
+    Ok(())
+}
+
+#[test]
+fn cancel_orders() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let owner = abs.environment().sender_addr();
+    let other = abs.environment().addr_make("other");
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+    let ntrn_asset = "ntrn".to_string();
+    let juno_asset = "juno".to_string();
+
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "atom"),
+    )?;
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "uosmo"),
+    )?;
+    app.limit_order(
+        ntrn_asset.clone(),
+        Decimal::one(),
+        juno_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(5, "ntrn"),
+    )?;
+
+    // make sure only the owner can cancel
     let err: OrderbookError = app
-        .market_order(
+        .call_as(&other)
+        .cancel_order(1)
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::NotOrderOwner(1));
+
+    // make sure unknown orders can't be cancelled
+    let err: OrderbookError = app.cancel_order(99).unwrap_err().downcast().unwrap();
+    assert_eq!(err, OrderbookError::OrderNotFound(99));
+
+    // cancelling refunds the escrowed quote of a bid
+    app.cancel_order(1)?;
+    assert_eq!(
+        abs.environment().balance(&owner, Some("atom".into()))?,
+        coins(1000, "atom")
+    );
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(bids_resp.bids.len(), 0);
+
+    // an order can only be cancelled once
+    let err: OrderbookError = app.cancel_order(1).unwrap_err().downcast().unwrap();
+    assert_eq!(err, OrderbookError::OrderClosed(1));
+
+    // cancelling a market only touches that market
+    app.cancel_all_orders(Some((osmo_asset.clone(), atom_asset.clone())))?;
+    assert_eq!(
+        abs.environment().balance(&owner, Some("uosmo".into()))?,
+        coins(1000, "uosmo")
+    );
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks.len(), 0);
+    let asks_resp: AsksResponse = app.asks(ntrn_asset.clone(), juno_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks.len(), 1);
+
+    // orders of other accounts are left alone
+    app.call_as(&other).cancel_all_orders(None)?;
+    let asks_resp: AsksResponse = app.asks(ntrn_asset.clone(), juno_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks.len(), 1);
+
+    app.cancel_all_orders(None)?;
+    assert_eq!(
+        abs.environment().balance(&owner, Some("ntrn".into()))?,
+        coins(1000, "ntrn")
+    );
+    let asks_resp: AsksResponse = app.asks(ntrn_asset.clone(), juno_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks.len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn book_sorted_and_paginated() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    for price in ["1.0", "3.0", "2.0", "3.0"] {
+        app.limit_order(
             osmo_asset.clone(),
+            Decimal::from_str(price)?,
             atom_asset.clone(),
-            "invalid",
-            &atom_coins,
+            Side::Buy,
+            None,
+            None,
+            None,
+            None,
+            &coins(10, "atom"),
+        )?;
+    }
+    for price in ["5.0", "4.0", "6.0", "4.0"] {
+        app.limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str(price)?,
+            atom_asset.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(10, "uosmo"),
+        )?;
+    }
+
+    // bids descend in price, asks ascend, and equal prices keep their arrival order
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    let bids: Vec<u64> = bids_resp.bids.iter().map(|bid| bid.id).collect();
+    assert_eq!(bids, vec![2, 4, 3, 1]);
+
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    let asks: Vec<u64> = asks_resp.asks.iter().map(|ask| ask.id).collect();
+    assert_eq!(asks, vec![6, 8, 5, 7]);
+
+    // pages pick up right after the last order returned
+    let page: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), Some(2), None)?;
+    let bids: Vec<u64> = page.bids.iter().map(|bid| bid.id).collect();
+    assert_eq!(bids, vec![2, 4]);
+
+    let last = page.bids.last().unwrap();
+    let page: BidsResponse = app.bids(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Some(2),
+        Some((last.price, last.id)),
+    )?;
+    let bids: Vec<u64> = page.bids.iter().map(|bid| bid.id).collect();
+    assert_eq!(bids, vec![3, 1]);
+
+    // other markets are not part of the response
+    let asks_resp: AsksResponse = app.asks("ntrn".to_string(), "juno".to_string(), None, None)?;
+    assert_eq!(asks_resp.asks.len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn ans_names_resolve_to_denoms() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let sender = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(100, IBC_ATOM))?;
+    abs.add_balance(&taker, &coins(100, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "cosmoshub>atom".to_string();
+
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "uosmo"),
+    )?;
+
+    // the order has to be paid in the denom the ANS entry points to
+    let err: OrderbookError = app
+        .call_as(&taker)
+        .limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str("2.0")?,
+            atom_asset.clone(),
+            Side::Buy,
+            None,
+            None,
+            None,
+            None,
+            &coins(20, "atom"),
         )
         .unwrap_err()
         .downcast()
         .unwrap();
-    assert_eq!(err, OrderbookError::InvalidSide("invalid".to_string()));
-
-    // make sure it works
-    let _ = app.market_order(osmo_asset.clone(), atom_asset.clone(), "buy", &atom_coins)?;
-
-    // // make sure balance of sender was updated
-    // let balances = app.account().query_balances()?;
-    // assert_eq!(balances, coins(1, "osmo"));
-
-    // let bids: BidsResponse = app.bids()?;
-    // println!("bids {:#?}", bids);
-
-    // assert_eq!(bids.bids.len(), 1);
-    // assert_eq!(
-    //     bids.bids[0],
-    //     (
-    //         asset.clone(),
-    //         vec![BidAsk {
-    //             account: sender,
-    //             price: Decimal::zero(),
-    //             quantity: Uint128::one(),
-    //         }]
-    //     )
-    // );
+    assert_eq!(err, OrderbookError::IncorrectAsset);
+
+    app.call_as(&taker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(20, IBC_ATOM),
+    )?;
+
+    let balance = abs.environment().balance(&taker, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(10));
+    let balance = abs.environment().balance(&sender, Some(IBC_ATOM.into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1020));
+
+    // refunds are paid in the resolved denom as well
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, IBC_ATOM),
+    )?;
+    let balance = abs.environment().balance(&sender, Some(IBC_ATOM.into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1010));
+
+    app.cancel_all_orders(None)?;
+    let balance = abs.environment().balance(&sender, Some(IBC_ATOM.into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1020));
+
+    Ok(())
+}
+
+#[test]
+fn cw20_orders() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let cw20 = env.cw20;
+    let sender = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(100, "atom"))?;
+
+    let test_asset = "test".to_string();
+    let atom_asset = "atom".to_string();
+    let osmo_asset = "uosmo".to_string();
+
+    // ask 100 test for atom by sending the tokens along
+    cw20.send(
+        Uint128::new(100),
+        app.address()?.to_string(),
+        to_json_binary(&OrderbookReceiveMsg::LimitOrder {
+            base: test_asset.clone(),
+            quote: atom_asset.clone(),
+            price: Decimal::from_str("2.0")?,
+            side: Side::Sell,
+            expires: None,
+            post_only: None,
+            time_in_force: None,
+        })?,
+    )?;
+    let asks_resp: AsksResponse = app.asks(test_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks[0].quantity, Uint128::new(100));
+
+    // a native bid takes half of it and is paid out in cw20
+    app.call_as(&taker).limit_order(
+        test_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(100, "atom"),
+    )?;
+    assert_eq!(cw20.balance(taker.to_string())?.balance, Uint128::new(50));
+    let balance = abs.environment().balance(&sender, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1100));
+
+    // the cw20 can be the quote of a market too
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        test_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "uosmo"),
+    )?;
+
+    // native funds don't pay for cw20 quoted orders
+    let err: OrderbookError = app
+        .market_order(
+            osmo_asset.clone(),
+            test_asset.clone(),
+            Side::Buy,
+            None,
+            &coins(10, "uosmo"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::IncorrectAsset);
+
+    cw20.call_as(&taker).send(
+        Uint128::new(10),
+        app.address()?.to_string(),
+        to_json_binary(&OrderbookReceiveMsg::MarketOrder {
+            base: osmo_asset.clone(),
+            quote: test_asset.clone(),
+            side: Side::Buy,
+        })?,
+    )?;
+    let balance = abs.environment().balance(&taker, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(10));
+    assert_eq!(cw20.balance(taker.to_string())?.balance, Uint128::new(40));
+    assert_eq!(cw20.balance(sender.to_string())?.balance, Uint128::new(910));
+
+    // cancelling refunds the unfilled cw20 escrow
+    app.cancel_all_orders(None)?;
+    assert_eq!(cw20.balance(sender.to_string())?.balance, Uint128::new(960));
+
+    assert_backed(&app)?;
+
+    Ok(())
+}
+
+#[test]
+fn tick_and_lot_sizes() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(1000, "uosmo"))?;
+
+    let base = "atom".to_string();
+    let quote = "uosmo".to_string();
+    app.create_market(
+        &base,
+        Uint128::new(10),
+        Uint128::new(100),
+        &quote,
+        Decimal::from_str("0.05")?,
+    )?;
+
+    let err: OrderbookError = app
+        .limit_order(
+            base.clone(),
+            Decimal::from_str("1.01")?,
+            quote.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(100, "atom"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::InvalidTickSize {
+            price: Decimal::from_str("1.01")?,
+            tick_size: Decimal::from_str("0.05")?,
+        }
+    );
+
+    let err: OrderbookError = app
+        .limit_order(
+            base.clone(),
+            Decimal::from_str("1.05")?,
+            quote.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(105, "atom"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::InvalidLotSize {
+            quantity: Uint128::new(105),
+            lot_size: Uint128::new(10),
+        }
+    );
+
+    let err: OrderbookError = app
+        .limit_order(
+            base.clone(),
+            Decimal::from_str("1.05")?,
+            quote.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(50, "atom"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::OrderTooSmall {
+            quantity: Uint128::new(50),
+            min_order_size: Uint128::new(100),
+        }
+    );
+
+    // bids are sized by the whole lots their quote buys at the limit price
+    let err: OrderbookError = app
+        .limit_order(
+            base.clone(),
+            Decimal::from_str("1.05")?,
+            quote.clone(),
+            Side::Buy,
+            None,
+            None,
+            None,
+            None,
+            &coins(100, "uosmo"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::OrderTooSmall {
+            quantity: Uint128::new(90),
+            min_order_size: Uint128::new(100),
+        }
+    );
+    app.limit_order(
+        base.clone(),
+        Decimal::from_str("1.05")?,
+        quote.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(105, "uosmo"),
+    )?;
+
+    app.limit_order(
+        base.clone(),
+        Decimal::from_str("1.5")?,
+        quote.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(200, "atom"),
+    )?;
+
+    // market buys are sized at the best ask
+    let err: OrderbookError = app
+        .call_as(&taker)
+        .market_order(
+            base.clone(),
+            quote.clone(),
+            Side::Buy,
+            None,
+            &coins(140, "uosmo"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::OrderTooSmall {
+            quantity: Uint128::new(90),
+            min_order_size: Uint128::new(100),
+        }
+    );
+
+    // 160 uosmo buys 106 atom, only whole lots are filled and the rest is refunded
+    app.call_as(&taker).market_order(
+        base.clone(),
+        quote.clone(),
+        Side::Buy,
+        None,
+        &coins(160, "uosmo"),
+    )?;
+    let balance = abs.environment().balance(&taker, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(100));
+    let balance = abs.environment().balance(&taker, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(850));
+
+    let asks: AsksResponse = app.asks(base.clone(), quote.clone(), None, None)?;
+    assert_eq!(asks.asks[0].quantity, Uint128::new(100));
+
+    Ok(())
+}
+
+#[test]
+fn zero_min_order_size() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+
+    let base = "atom".to_string();
+    let quote = "uosmo".to_string();
+    app.create_market(
+        &base,
+        Uint128::one(),
+        Uint128::zero(),
+        &quote,
+        Decimal::from_str("0.5")?,
+    )?;
+
+    // 2 quote doesn't buy a single lot at 3.0, so there is nothing to rest
+    let err: OrderbookError = app
+        .limit_order(
+            base.clone(),
+            Decimal::from_str("3.0")?,
+            quote.clone(),
+            Side::Buy,
+            None,
+            None,
+            None,
+            None,
+            &coins(2, "uosmo"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::ZeroQuantity);
+
+    let err: OrderbookError = app
+        .stop_order(
+            base.clone(),
+            quote.clone(),
+            Side::Buy,
+            Decimal::from_str("3.0")?,
+            None,
+            None,
+            &coins(2, "uosmo"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::ZeroQuantity);
+
+    Ok(())
+}
+
+#[test]
+fn trading_fees() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let sender = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    let treasury = abs.environment().addr_make("treasury");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+    abs.add_balance(&taker, &coins(1000, "uosmo"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    // 1% for makers, 2% for takers
+    app.update_config(
+        Some(FeeRecipient::Address(treasury.to_string())),
+        None,
+        Some(100),
+        Some(200),
+    )?;
+
+    // a taking bid pays its fee on top of the fill
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(100, "uosmo"),
+    )?;
+    let resp = app.call_as(&taker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(204, "atom"),
+    )?;
+    assert_eq!(resp.event_attr_value("wasm-fill", "maker_fee")?, "2");
+    assert_eq!(resp.event_attr_value("wasm-fill", "taker_fee")?, "4");
+
+    let balance = abs.environment().balance(&taker, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1100));
+    let balance = abs.environment().balance(&taker, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(796));
+    // the maker fee comes out of the seller's proceeds
+    let balance = abs.environment().balance(&sender, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1198));
+    let balance = abs.environment().balance(&treasury, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(6));
+
+    // a resting bid keeps enough quote to cover its maker fee
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(101, "atom"),
+    )?;
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        &coins(100, "uosmo"),
+    )?;
+
+    let balance = abs.environment().balance(&taker, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(894));
+    let balance = abs.environment().balance(&sender, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1000));
+    let balance = abs.environment().balance(&treasury, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(9));
+    let bids: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(bids.bids.len(), 0);
+
+    // without a recipient the fees stay in the account
+    app.update_config(Some(FeeRecipient::Account), None, None, None)?;
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(100, "uosmo"),
+    )?;
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(102, "atom"),
+    )?;
+    let account = app.account().proxy()?;
+    let balance = abs.environment().balance(&account, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(3));
+
+    assert_backed(&app)?;
+
+    Ok(())
+}
+
+#[test]
+fn fee_tiers() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let taker = abs.environment().addr_make("taker");
+    let treasury = abs.environment().addr_make("treasury");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    let tiers = vec![
+        FeeTier {
+            min_volume: Uint128::new(200),
+            maker_fee_bps: 50,
+            taker_fee_bps: 100,
+        },
+        FeeTier {
+            min_volume: Uint128::new(1000),
+            maker_fee_bps: 0,
+            taker_fee_bps: 50,
+        },
+    ];
+
+    let err: OrderbookError = app
+        .update_config(
+            None,
+            Some(tiers.iter().rev().cloned().collect()),
+            None,
+            None,
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::UnsortedFeeTiers);
+
+    app.update_config(
+        Some(FeeRecipient::Address(treasury.to_string())),
+        Some(tiers),
+        Some(100),
+        Some(200),
+    )?;
+
+    let tier: FeeTierResponse = app.fee_tier(taker.to_string())?;
+    assert_eq!(tier.volume, Uint128::zero());
+    assert_eq!(tier.tier, None);
+    assert_eq!(tier.taker_fee_bps, 200);
+
+    // the first trade pays the base rates
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(100, "uosmo"),
+    )?;
+    let resp = app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(204, "atom"),
+    )?;
+    assert_eq!(resp.event_attr_value("wasm-fill", "maker_fee")?, "2");
+    assert_eq!(resp.event_attr_value("wasm-fill", "taker_fee")?, "4");
+
+    // both sides traded 200 quote, which reaches the first tier
+    let tier: FeeTierResponse = app.fee_tier(taker.to_string())?;
+    assert_eq!(tier.volume, Uint128::new(200));
+    assert_eq!(tier.tier, Some(0));
+    assert_eq!(tier.maker_fee_bps, 50);
+    assert_eq!(tier.taker_fee_bps, 100);
+
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(100, "uosmo"),
+    )?;
+    let resp = app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(202, "atom"),
+    )?;
+    assert_eq!(resp.event_attr_value("wasm-fill", "maker_fee")?, "1");
+    assert_eq!(resp.event_attr_value("wasm-fill", "taker_fee")?, "2");
+
+    let balance = abs.environment().balance(&taker, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(200));
+    let balance = abs.environment().balance(&treasury, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(9));
+
+    // volume falls out of the window after 30 days
+    abs.environment().wait_seconds(29 * 86_400)?;
+    let tier: FeeTierResponse = app.fee_tier(taker.to_string())?;
+    assert_eq!(tier.volume, Uint128::new(400));
+    abs.environment().wait_seconds(86_400)?;
+    let tier: FeeTierResponse = app.fee_tier(taker.to_string())?;
+    assert_eq!(tier.volume, Uint128::zero());
+    assert_eq!(tier.tier, None);
+
+    Ok(())
+}
+
+#[test]
+fn withdraw_fees() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let taker = abs.environment().addr_make("taker");
+    let treasury = abs.environment().addr_make("treasury");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+    let proxy = app.account().proxy()?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    // fees stay in the account
+    app.update_config(None, None, Some(100), Some(200))?;
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(100, "uosmo"),
+    )?;
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(204, "atom"),
+    )?;
+
+    // next to the escrow of a resting bid
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(50, "atom"),
+    )?;
+    let balance = abs.environment().balance(&proxy, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(56));
+
+    let revenue: ProtocolRevenueResponse = app.protocol_revenue()?;
+    assert_eq!(revenue.revenue, vec![AnsAsset::new("atom", 6u128)]);
+
+    // the escrow can't be withdrawn as fees
+    let err: OrderbookError = app
+        .withdraw_fees(vec![AnsAsset::new("atom", 7u128)], treasury.to_string())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::InsufficientRevenue {
+            asset: "atom".to_string(),
+            available: Uint128::new(6),
+        }
+    );
+
+    let err: OrderbookError = app
+        .call_as(&taker)
+        .withdraw_fees(vec![AnsAsset::new("atom", 6u128)], taker.to_string())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::Admin(AdminError::NotAdmin {}));
+
+    app.withdraw_fees(vec![AnsAsset::new("atom", 6u128)], treasury.to_string())?;
+    let balance = abs.environment().balance(&treasury, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(6));
+    let balance = abs.environment().balance(&proxy, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(50));
+
+    let revenue: ProtocolRevenueResponse = app.protocol_revenue()?;
+    assert_eq!(revenue.revenue, vec![AnsAsset::new("atom", 0u128)]);
+
+    // the bid is still fully backed
+    app.cancel_all_orders(None)?;
+    let balance = abs.environment().balance(&proxy, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::zero());
+
+    assert_backed(&app)?;
+
+    Ok(())
+}
+
+#[test]
+fn balance_ledger() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let cw20 = env.cw20;
+    let sender = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+    let proxy = app.account().proxy()?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    app.deposit(&coins(100, "uosmo"))?;
+    app.call_as(&taker).deposit(&coins(100, "atom"))?;
+    let balances: BalancesResponse = app.balances(sender.to_string())?;
+    assert_eq!(balances.balances, vec![AnsAsset::new("uosmo", 100u128)]);
+
+    // orders lock from the free balance, nothing is sent along
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        Some(Uint128::new(50)),
+        None,
+        None,
+        &[],
+    )?;
+    let balances: BalancesResponse = app.balances(sender.to_string())?;
+    assert_eq!(balances.balances, vec![AnsAsset::new("uosmo", 50u128)]);
+
+    let err: OrderbookError = app
+        .limit_order(
+            osmo_asset.clone(),
+            Decimal::one(),
+            atom_asset.clone(),
+            Side::Sell,
+            None,
+            Some(Uint128::new(60)),
+            None,
+            None,
+            &[],
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::InsufficientBalance {
+            asset: "uosmo".to_string(),
+            available: Uint128::new(50),
+        }
+    );
+
+    let err: OrderbookError = app
+        .limit_order(
+            osmo_asset.clone(),
+            Decimal::one(),
+            atom_asset.clone(),
+            Side::Sell,
+            None,
+            Some(Uint128::new(10)),
+            None,
+            None,
+            &coins(10, "uosmo"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::Payment(PaymentError::NonPayable {}));
+
+    // fills move balances without any transfer
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        Some(Uint128::new(30)),
+        &[],
+    )?;
+    let balances: BalancesResponse = app.balances(sender.to_string())?;
+    assert_eq!(
+        balances.balances,
+        vec![
+            AnsAsset::new("atom", 30u128),
+            AnsAsset::new("uosmo", 50u128)
+        ]
+    );
+    let balances: BalancesResponse = app.balances(taker.to_string())?;
+    assert_eq!(
+        balances.balances,
+        vec![
+            AnsAsset::new("atom", 70u128),
+            AnsAsset::new("uosmo", 30u128)
+        ]
+    );
+    let balance = abs.environment().balance(&proxy, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(100));
+    let balance = abs.environment().balance(&proxy, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(100));
+
+    // cancelling returns the rest of the escrow to the balance
+    app.cancel_all_orders(None)?;
+    let balances: BalancesResponse = app.balances(sender.to_string())?;
+    assert_eq!(
+        balances.balances,
+        vec![
+            AnsAsset::new("atom", 30u128),
+            AnsAsset::new("uosmo", 70u128)
+        ]
+    );
+
+    app.call_as(&taker)
+        .withdraw(Uint128::new(30), osmo_asset.clone())?;
+    let balance = abs.environment().balance(&taker, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(30));
+    let err: OrderbookError = app
+        .call_as(&taker)
+        .withdraw(Uint128::new(1), osmo_asset.clone())
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::InsufficientBalance {
+            asset: "uosmo".to_string(),
+            available: Uint128::zero(),
+        }
+    );
+
+    // cw20 tokens are deposited through a send
+    cw20.send(
+        Uint128::new(10),
+        app.address()?.to_string(),
+        to_json_binary(&OrderbookReceiveMsg::Deposit {})?,
+    )?;
+    let balances: BalancesResponse = app.balances(sender.to_string())?;
+    assert_eq!(balances.balances[1], AnsAsset::new("test", 10u128));
+
+    assert_backed(&app)?;
+
+    Ok(())
+}
+
+#[test]
+fn dust_remainder_refunded() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("3.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(2, "uosmo"),
+    )?;
+
+    // 2 of the 8 atom are left after the fill, not enough for another lot at 3.0
+    let resp = app.call_as(&taker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("3.0")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(8, "atom"),
+    )?;
+    let order_id: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+    let order = app.order(order_id)?;
+    assert_eq!(order.status, OrderStatus::Filled);
+    assert_eq!(order.remaining_quantity, Uint128::zero());
+
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert!(bids_resp.bids.is_empty());
+    let balance = abs.environment().balance(&taker, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(994));
+    assert_backed(&app)?;
+
+    Ok(())
+}
+
+#[test]
+fn dust_bid_refunded() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    let resp = app.call_as(&taker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("3.0")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(3, "atom"),
+    )?;
+    let order_id: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+
+    // with a maker fee the bid no longer pays for its lot, the seller refunds it without a trade
+    app.update_config(None, None, Some(100), None)?;
+    let resp = app.market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        &coins(1, "uosmo"),
+    )?;
+    assert_eq!(resp.event_attr_value("wasm", "fills")?, "0");
+    assert_eq!(app.order(order_id)?.status, OrderStatus::Cancelled);
+    let balance = abs.environment().balance(&taker, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1000));
+    assert_backed(&app)?;
+
+    Ok(())
+}
+
+#[test]
+fn remainder_rests_at_maker_fee() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    // a lot at 3.0 is just paid for without the taker fee, which a resting bid doesn't pay
+    app.update_config(None, None, Some(0), Some(100))?;
+    let resp = app.call_as(&taker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("3.0")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(3, "atom"),
+    )?;
+    let order_id: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+    assert_eq!(app.order(order_id)?.status, OrderStatus::Open);
+
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(bids_resp.bids.len(), 1);
+    assert_eq!(bids_resp.bids[0].id, order_id);
+
+    app.market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        &coins(1, "uosmo"),
+    )?;
+    assert_eq!(app.order(order_id)?.status, OrderStatus::Filled);
+    let balance = abs.environment().balance(&taker, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1));
+    assert_backed(&app)?;
+
+    Ok(())
+}
+
+#[test]
+fn time_in_force() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    for price in ["1.0", "2.0"] {
+        app.limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str(price)?,
+            atom_asset.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(10, "uosmo"),
+        )?;
+    }
+
+    // immediate-or-cancel takes what crosses and refunds the rest
+    let resp = app.call_as(&taker).limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        Some(TimeInForce::ImmediateOrCancel),
+        &coins(30, "atom"),
+    )?;
+    let order_id: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+    let order = app.order(order_id)?;
+    assert_eq!(order.status, OrderStatus::Cancelled);
+    // the unfilled 20 atom went back to the taker, nothing rests
+    assert_eq!(order.remaining_quantity, Uint128::zero());
+    let balance = abs.environment().balance(&taker, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(990));
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(bids_resp.bids.len(), 0);
+
+    // fill-or-kill reverts when the book can't fill all of it
+    let err: OrderbookError = app
+        .call_as(&taker)
+        .limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str("2.0")?,
+            atom_asset.clone(),
+            Side::Buy,
+            None,
+            None,
+            None,
+            Some(TimeInForce::FillOrKill),
+            &coins(30, "atom"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::FillOrKillUnfilled);
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks[0].quantity, Uint128::new(10));
+
+    let resp = app.call_as(&taker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        Some(TimeInForce::FillOrKill),
+        &coins(20, "atom"),
+    )?;
+    let order_id: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+    assert_eq!(app.order(order_id)?.status, OrderStatus::Filled);
+    let balance = abs.environment().balance(&taker, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(20));
+
+    // nothing to cross, nothing rests
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("5.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        Some(TimeInForce::ImmediateOrCancel),
+        &coins(10, "uosmo"),
+    )?;
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks.len(), 0);
+
+    assert_backed(&app)?;
+
+    Ok(())
+}
+
+#[test]
+fn post_only() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let maker = abs.environment().addr_make("maker");
+    abs.add_balance(&maker, &coins(1000, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "uosmo"),
+    )?;
+
+    // crossing is rejected with its own error
+    let err: OrderbookError = app
+        .call_as(&maker)
+        .limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str("2.0")?,
+            atom_asset.clone(),
+            Side::Buy,
+            None,
+            None,
+            Some(PostOnly::Reject),
+            None,
+            &coins(40, "atom"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::PostOnlyWouldCross {
+            price: Decimal::from_str("2.0")?,
+            best: Decimal::from_str("2.0")?,
+        }
+    );
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks[0].quantity, Uint128::new(10));
+
+    // or moved one tick behind the best ask
+    app.call_as(&maker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("3.0")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        Some(PostOnly::Reprice),
+        None,
+        &coins(40, "atom"),
+    )?;
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(bids_resp.bids[0].price, Decimal::from_str("1.99")?);
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks[0].quantity, Uint128::new(10));
+
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        Some(PostOnly::Reprice),
+        None,
+        &coins(10, "uosmo"),
+    )?;
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks.len(), 2);
+    assert_eq!(asks_resp.asks[1].price, Decimal::from_str("2.0")?);
+
+    // an order that doesn't cross rests as is
+    app.call_as(&maker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("1.5")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        Some(PostOnly::Reject),
+        None,
+        &coins(15, "atom"),
+    )?;
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(bids_resp.bids[1].price, Decimal::from_str("1.5")?);
+
+    let err: OrderbookError = app
+        .call_as(&maker)
+        .limit_order(
+            osmo_asset.clone(),
+            Decimal::one(),
+            atom_asset.clone(),
+            Side::Buy,
+            None,
+            None,
+            Some(PostOnly::Reject),
+            Some(TimeInForce::ImmediateOrCancel),
+            &coins(10, "atom"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::PostOnlyNotResting);
+
+    Ok(())
+}
+
+#[test]
+fn expiring_orders() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let sender = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+    let block = abs.environment().block_info()?;
+
+    let err: OrderbookError = app
+        .limit_order(
+            osmo_asset.clone(),
+            Decimal::one(),
+            atom_asset.clone(),
+            Side::Sell,
+            Some(Expiration::AtHeight(block.height)),
+            None,
+            None,
+            None,
+            &coins(10, "uosmo"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::AlreadyExpired(Expiration::AtHeight(block.height))
+    );
+
+    for (price, expires) in [
+        ("1.0", Expiration::AtHeight(block.height + 5)),
+        ("2.0", Expiration::AtTime(block.time.plus_seconds(100))),
+        ("3.0", Expiration::Never {}),
+        ("4.0", Expiration::AtTime(block.time.plus_seconds(100))),
+    ] {
+        app.limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str(price)?,
+            atom_asset.clone(),
+            Side::Sell,
+            Some(expires),
+            None,
+            None,
+            None,
+            &coins(10, "uosmo"),
+        )?;
+    }
+
+    // expired orders disappear from the book right away
+    abs.environment().wait_blocks(5)?;
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks.len(), 3);
+    assert_eq!(asks_resp.asks[0].id, 2);
+    assert_eq!(app.order(1)?.status, OrderStatus::Expired);
+    let open: OrdersResponse = app.open_orders(sender.to_string(), None, None, None)?;
+    assert_eq!(open.orders[0].id, 1);
+    assert_eq!(open.orders[0].status, OrderStatus::Expired);
+
+    // and are refunded when the matcher runs into them
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(20, "atom"),
+    )?;
+    let balance = abs.environment().balance(&taker, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(10));
+    let balance = abs.environment().balance(&sender, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(970));
+    assert_eq!(app.order(2)?.status, OrderStatus::Filled);
+
+    // or when anyone prunes them
+    abs.environment().wait_seconds(100)?;
+    let resp = app
+        .call_as(&taker)
+        .prune_expired((osmo_asset.clone(), atom_asset.clone()), None)?;
+    assert_eq!(resp.event_attr_value("wasm", "expired")?, "1");
+    assert_eq!(resp.event_attr_value("wasm-expire", "order_id")?, "4");
+    let balance = abs.environment().balance(&sender, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(980));
+
+    let resp = app.prune_expired((osmo_asset.clone(), atom_asset.clone()), None)?;
+    assert_eq!(resp.event_attr_value("wasm", "expired")?, "0");
+    let asks_resp: AsksResponse = app.asks(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(asks_resp.asks.len(), 1);
+    assert_eq!(asks_resp.asks[0].id, 3);
+
+    assert_backed(&app)?;
+
+    Ok(())
+}
+
+#[test]
+fn stop_orders() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let sender = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    let stopper = abs.environment().addr_make("stopper");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+    abs.add_balance(&stopper, &coins(1000, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    for price in ["1.0", "2.0", "3.0"] {
+        app.limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str(price)?,
+            atom_asset.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(10, "uosmo"),
+        )?;
+    }
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(10, "atom"),
+    )?;
+
+    // a stop the last price already reached would fire right away
+    let err: OrderbookError = app
+        .call_as(&stopper)
+        .stop_order(
+            osmo_asset.clone(),
+            atom_asset.clone(),
+            Side::Buy,
+            Decimal::one(),
+            None,
+            None,
+            &coins(10, "atom"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::StopWouldTrigger {
+            trigger_price: Decimal::one(),
+            last_price: Decimal::one(),
+        }
+    );
+
+    // a stop-market and a stop-limit buy, both off the book until the price reaches 2.0
+    let resp = app.call_as(&stopper).stop_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        Decimal::from_str("2.0")?,
+        None,
+        None,
+        &coins(30, "atom"),
+    )?;
+    let market_stop: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+    let resp = app.call_as(&stopper).stop_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        Decimal::from_str("2.0")?,
+        None,
+        Some(Decimal::from_str("2.0")?),
+        &coins(40, "atom"),
+    )?;
+    let limit_stop: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert!(bids_resp.bids.is_empty());
+    assert_backed(&app)?;
+
+    // the trade at 2.0 fires both: the market stop buys the ask at 3.0, the limit stop finds
+    // nothing at 2.0 left and rests
+    let resp = app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(20, "atom"),
+    )?;
+    assert_eq!(
+        resp.event_attr_value("wasm-stop_triggered", "order_id")?,
+        market_stop.to_string()
+    );
+    let balance = abs.environment().balance(&stopper, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(10));
+    assert_eq!(app.stop(market_stop)?.status, StopStatus::Triggered);
+    assert_eq!(app.stop(limit_stop)?.status, StopStatus::Triggered);
+    assert_eq!(app.order(limit_stop)?.status, OrderStatus::Open);
+
+    let bids_resp: BidsResponse = app.bids(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(bids_resp.bids.len(), 1);
+    assert_eq!(bids_resp.bids[0].id, limit_stop);
+    assert_eq!(bids_resp.bids[0].quantity, Uint128::new(40));
+
+    // pending stops are cancelled like orders and refunded
+    let resp = app.call_as(&stopper).stop_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Sell,
+        Decimal::one(),
+        None,
+        None,
+        &coins(10, "uosmo"),
+    )?;
+    let sell_stop: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+    let err: OrderbookError = app.cancel_order(sell_stop).unwrap_err().downcast().unwrap();
+    assert_eq!(err, OrderbookError::NotOrderOwner(sell_stop));
+    assert_backed(&app)?;
+
+    app.call_as(&stopper).cancel_order(sell_stop)?;
+    assert_eq!(app.stop(sell_stop)?.status, StopStatus::Cancelled);
+    let balance = abs.environment().balance(&stopper, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(10));
+    let err: OrderbookError = app
+        .call_as(&stopper)
+        .cancel_order(market_stop)
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(err, OrderbookError::OrderClosed(market_stop));
+
+    // the owner can list pending stops and cancel them along with its resting orders
+    let resp = app.call_as(&stopper).stop_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Sell,
+        Decimal::one(),
+        None,
+        None,
+        &coins(10, "uosmo"),
+    )?;
+    let pending_stop: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+    let stops: StopsResponse = app.stops(stopper.to_string(), None, None, None)?;
+    let ids: Vec<u64> = stops.stops.iter().map(|stop| stop.id).collect();
+    assert_eq!(ids, vec![pending_stop]);
+
+    let resp = app
+        .call_as(&stopper)
+        .cancel_all_orders(Some((osmo_asset.clone(), atom_asset.clone())))?;
+    assert_eq!(resp.event_attr_value("wasm", "cancelled")?, "2");
+    assert_eq!(app.stop(pending_stop)?.status, StopStatus::Cancelled);
+    let stops: StopsResponse = app.stops(stopper.to_string(), None, None, None)?;
+    assert!(stops.stops.is_empty());
+    let balance = abs.environment().balance(&stopper, Some("uosmo".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(10));
+
+    let balance = abs.environment().balance(&sender, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1060));
+    assert_backed(&app)?;
+
+    Ok(())
+}
+
+#[test]
+fn trailing_stops() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let taker = abs.environment().addr_make("taker");
+    let stopper = abs.environment().addr_make("stopper");
+    abs.add_balance(&taker, &coins(1000, "atom"))?;
+    abs.add_balance(&stopper, &coins(1000, "atom"))?;
+    abs.add_balance(&stopper, &coins(1000, "uosmo"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    // there's nothing to follow before the first trade
+    let err: OrderbookError = app
+        .call_as(&stopper)
+        .trailing_stop(
+            osmo_asset.clone(),
+            atom_asset.clone(),
+            Side::Sell,
+            Trail::Offset(Decimal::from_str("0.5")?),
+            None,
+            &coins(10, "uosmo"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::NoLastPrice {
+            base: osmo_asset.clone(),
+            quote: atom_asset.clone(),
+        }
+    );
+
+    let trade_at = |price: &str| -> anyhow::Result<()> {
+        let price = Decimal::from_str(price)?;
+        app.limit_order(
+            osmo_asset.clone(),
+            price,
+            atom_asset.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(10, "uosmo"),
+        )?;
+        app.call_as(&taker).market_order(
+            osmo_asset.clone(),
+            atom_asset.clone(),
+            Side::Buy,
+            None,
+            &coins(Uint128::new(10).mul_ceil(price).u128(), "atom"),
+        )?;
+        Ok(())
+    };
+    trade_at("1.0")?;
+
+    let err: OrderbookError = app
+        .call_as(&stopper)
+        .trailing_stop(
+            osmo_asset.clone(),
+            atom_asset.clone(),
+            Side::Sell,
+            Trail::Offset(Decimal::one()),
+            None,
+            &coins(10, "uosmo"),
+        )
+        .unwrap_err()
+        .downcast()
+        .unwrap();
+    assert_eq!(
+        err,
+        OrderbookError::InvalidTrail(Trail::Offset(Decimal::one()))
+    );
+
+    let resp = app.call_as(&stopper).trailing_stop(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Sell,
+        Trail::Offset(Decimal::from_str("0.5")?),
+        None,
+        &coins(10, "uosmo"),
+    )?;
+    let sell_stop: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+    let stop = app.stop(sell_stop)?;
+    assert_eq!(stop.mark, Some(Decimal::one()));
+    assert_eq!(stop.trigger_price, Decimal::from_str("0.5")?);
+
+    // a new high drags the trigger up behind it
+    trade_at("2.0")?;
+    let stop = app.stop(sell_stop)?;
+    assert_eq!(stop.mark, Some(Decimal::from_str("2.0")?));
+    assert_eq!(stop.trigger_price, Decimal::from_str("1.5")?);
+    let stops: StopsResponse = app.stops(stopper.to_string(), None, None, None)?;
+    let ids: Vec<u64> = stops.stops.iter().map(|stop| stop.id).collect();
+    assert_eq!(ids, vec![sell_stop]);
+
+    let resp = app.call_as(&stopper).trailing_stop(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        Trail::Percent(Decimal::from_str("50")?),
+        None,
+        &coins(30, "atom"),
+    )?;
+    let buy_stop: u64 = resp.event_attr_value("wasm", "order_id")?.parse()?;
+    assert_eq!(app.stop(buy_stop)?.trigger_price, Decimal::from_str("3.0")?);
+    assert_backed(&app)?;
+
+    // retracing to the trigger sells at market, the new low pulls the buy stop's trigger down
+    app.call_as(&taker).limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("1.5")?,
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(40, "atom"),
+    )?;
+    let resp = app.market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        &coins(10, "uosmo"),
+    )?;
+    assert_eq!(
+        resp.event_attr_value("wasm-stop_triggered", "order_id")?,
+        sell_stop.to_string()
+    );
+    assert_eq!(app.stop(sell_stop)?.status, StopStatus::Triggered);
+    let balance = abs.environment().balance(&stopper, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(985));
+
+    let stop = app.stop(buy_stop)?;
+    assert_eq!(stop.mark, Some(Decimal::from_str("1.5")?));
+    assert_eq!(stop.trigger_price, Decimal::from_str("2.25")?);
+
+    // a stop that moved is still the owner's to cancel with the rest
+    let stops: StopsResponse = app.stops(stopper.to_string(), None, None, None)?;
+    let ids: Vec<u64> = stops.stops.iter().map(|stop| stop.id).collect();
+    assert_eq!(ids, vec![buy_stop]);
+    app.call_as(&stopper).cancel_all_orders(None)?;
+    assert_eq!(app.stop(buy_stop)?.status, StopStatus::Cancelled);
+    let balance = abs.environment().balance(&stopper, Some("atom".into()))?;
+    assert_eq!(balance[0].amount, Uint128::new(1015));
+    assert_backed(&app)?;
 
     Ok(())
 }
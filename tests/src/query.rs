@@ -0,0 +1,340 @@
+use std::str::FromStr;
+
+use orderbook::{
+    msg::{
+        AssetBacking, DepthLevel, DepthResponse, InvariantsResponse, LastPriceResponse,
+        OrderbookExecuteMsgFns, OrderbookQueryMsgFns, OrdersResponse, Side, TradesResponse,
+    },
+    state::{Order, OrderStatus, Trade},
+};
+
+use abstract_app::objects::{AnsAsset, AssetEntry};
+use abstract_client::Environment;
+
+use cosmwasm_std::{coin, coins, Decimal, Uint128};
+
+// Use prelude to get all the necessary imports
+use cw_orch::{anyhow, prelude::*};
+
+use super::common::{assert_backed, TestEnv};
+
+#[test]
+fn depth() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    // an empty book has no prices
+    let depth: DepthResponse = app.depth(osmo_asset.clone(), 5, atom_asset.clone())?;
+    assert_eq!(
+        depth,
+        DepthResponse {
+            bids: vec![],
+            asks: vec![],
+            best_bid: None,
+            best_ask: None,
+            mid: None,
+            spread: None,
+        }
+    );
+
+    for (price, quantity) in [("2.0", 10), ("2.0", 4), ("1.0", 10)] {
+        app.limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str(price)?,
+            atom_asset.clone(),
+            Side::Buy,
+            None,
+            None,
+            None,
+            None,
+            &coins(quantity, "atom"),
+        )?;
+    }
+    for (price, quantity) in [("3.0", 3), ("3.0", 4), ("4.0", 5), ("5.0", 1)] {
+        app.limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str(price)?,
+            atom_asset.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(quantity, "uosmo"),
+        )?;
+    }
+
+    // levels are aggregated in base quantity, best price first
+    let depth: DepthResponse = app.depth(osmo_asset.clone(), 2, atom_asset.clone())?;
+    assert_eq!(
+        depth,
+        DepthResponse {
+            bids: vec![
+                DepthLevel {
+                    price: Decimal::from_str("2.0")?,
+                    quantity: Uint128::new(7),
+                    orders: 2,
+                },
+                DepthLevel {
+                    price: Decimal::one(),
+                    quantity: Uint128::new(10),
+                    orders: 1,
+                },
+            ],
+            asks: vec![
+                DepthLevel {
+                    price: Decimal::from_str("3.0")?,
+                    quantity: Uint128::new(7),
+                    orders: 2,
+                },
+                DepthLevel {
+                    price: Decimal::from_str("4.0")?,
+                    quantity: Uint128::new(5),
+                    orders: 1,
+                },
+            ],
+            best_bid: Some(Decimal::from_str("2.0")?),
+            best_ask: Some(Decimal::from_str("3.0")?),
+            mid: Some(Decimal::from_str("2.5")?),
+            spread: Some(Decimal::one()),
+        }
+    );
+
+    Ok(())
+}
+
+#[test]
+fn last_price_and_trades() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let maker = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(100, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    let last_price: LastPriceResponse = app.last_price(osmo_asset.clone(), atom_asset.clone())?;
+    assert_eq!(last_price.price, None);
+
+    for price in ["2.0", "3.0"] {
+        app.limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str(price)?,
+            atom_asset.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(5, "uosmo"),
+        )?;
+    }
+
+    // sweeps both asks, the last fill sets the price
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(25, "atom"),
+    )?;
+
+    let last_price: LastPriceResponse = app.last_price(osmo_asset.clone(), atom_asset.clone())?;
+    assert_eq!(last_price.price, Some(Decimal::from_str("3.0")?));
+
+    let block = abs.environment().block_info()?;
+    let trades: TradesResponse = app.trades(osmo_asset.clone(), atom_asset.clone(), None, None)?;
+    assert_eq!(
+        trades.trades,
+        vec![
+            Trade {
+                id: 2,
+                price: Decimal::from_str("3.0")?,
+                quantity: Uint128::new(5),
+                taker_side: Side::Buy,
+                maker: maker.clone(),
+                taker: taker.clone(),
+                block_height: block.height,
+                time: block.time,
+            },
+            Trade {
+                id: 1,
+                price: Decimal::from_str("2.0")?,
+                quantity: Uint128::new(5),
+                taker_side: Side::Buy,
+                maker: maker.clone(),
+                taker: taker.clone(),
+                block_height: block.height,
+                time: block.time,
+            },
+        ]
+    );
+
+    // pages continue with older trades
+    let trades: TradesResponse =
+        app.trades(osmo_asset.clone(), atom_asset.clone(), None, Some(2))?;
+    assert_eq!(trades.trades.len(), 1);
+    assert_eq!(trades.trades[0].id, 1);
+
+    Ok(())
+}
+
+#[test]
+fn open_orders_and_status() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let owner = abs.environment().sender_addr();
+    let taker = abs.environment().addr_make("taker");
+    abs.add_balance(&taker, &coins(100, "atom"))?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    for price in ["2.0", "3.0", "4.0"] {
+        app.limit_order(
+            osmo_asset.clone(),
+            Decimal::from_str(price)?,
+            atom_asset.clone(),
+            Side::Sell,
+            None,
+            None,
+            None,
+            None,
+            &coins(10, "uosmo"),
+        )?;
+    }
+    app.limit_order(
+        "ntrn".to_string(),
+        Decimal::one(),
+        "juno".to_string(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(10, "ntrn"),
+    )?;
+
+    // fills the first ask and half of the second
+    app.call_as(&taker).market_order(
+        osmo_asset.clone(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        &coins(35, "atom"),
+    )?;
+    app.cancel_order(3)?;
+
+    let order: Order = app.order(1)?;
+    assert_eq!(order.status, OrderStatus::Filled);
+    assert_eq!(order.remaining_quantity, Uint128::zero());
+
+    let order: Order = app.order(2)?;
+    assert_eq!(order.status, OrderStatus::PartiallyFilled);
+    assert_eq!(order.original_quantity, Uint128::new(10));
+    assert_eq!(order.remaining_quantity, Uint128::new(5));
+
+    let order: Order = app.order(3)?;
+    assert_eq!(order.status, OrderStatus::Cancelled);
+
+    let order: Order = app.order(4)?;
+    assert_eq!(order.status, OrderStatus::Open);
+
+    // only live orders are listed
+    let open: OrdersResponse = app.open_orders(owner.to_string(), None, None, None)?;
+    let ids: Vec<u64> = open.orders.iter().map(|order| order.id).collect();
+    assert_eq!(ids, vec![2, 4]);
+
+    let open: OrdersResponse = app.open_orders(
+        owner.to_string(),
+        None,
+        Some(("ntrn".to_string(), "juno".to_string())),
+        None,
+    )?;
+    let ids: Vec<u64> = open.orders.iter().map(|order| order.id).collect();
+    assert_eq!(ids, vec![4]);
+
+    let open: OrdersResponse = app.open_orders(owner.to_string(), None, None, Some(2))?;
+    let ids: Vec<u64> = open.orders.iter().map(|order| order.id).collect();
+    assert_eq!(ids, vec![4]);
+
+    let open: OrdersResponse = app.open_orders(taker.to_string(), None, None, None)?;
+    assert_eq!(open.orders.len(), 0);
+
+    Ok(())
+}
+
+#[test]
+fn invariants() -> anyhow::Result<()> {
+    let env = TestEnv::setup()?;
+    let app = env.app;
+    let abs = env.abs;
+    let proxy = app.account().proxy()?;
+
+    let osmo_asset = "uosmo".to_string();
+    let atom_asset = "atom".to_string();
+
+    // nothing owed, nothing to back
+    let invariants: InvariantsResponse = app.invariants()?;
+    assert_eq!(invariants.assets, vec![]);
+
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::one(),
+        atom_asset.clone(),
+        Side::Buy,
+        None,
+        None,
+        None,
+        None,
+        &coins(50, "atom"),
+    )?;
+    app.limit_order(
+        osmo_asset.clone(),
+        Decimal::from_str("2.0")?,
+        atom_asset.clone(),
+        Side::Sell,
+        None,
+        None,
+        None,
+        None,
+        &coins(20, "uosmo"),
+    )?;
+    app.deposit(&coins(10, "uosmo"))?;
+
+    let invariants: InvariantsResponse = app.invariants()?;
+    assert_eq!(
+        invariants.assets,
+        vec![
+            AssetBacking {
+                asset: AssetEntry::new("atom"),
+                locked: Uint128::new(50),
+                balances: Uint128::zero(),
+                fees: Uint128::zero(),
+                held: Uint128::new(50),
+            },
+            AssetBacking {
+                asset: AssetEntry::new("uosmo"),
+                locked: Uint128::new(20),
+                balances: Uint128::new(10),
+                fees: Uint128::zero(),
+                held: Uint128::new(30),
+            },
+        ]
+    );
+    assert_backed(&app)?;
+
+    // funds leaving the account behind the app's back show up as a shortfall
+    abs.environment()
+        .set_balance(&proxy, vec![coin(40, "atom"), coin(30, "uosmo")])?;
+    let invariants: InvariantsResponse = app.invariants()?;
+    assert_eq!(invariants.shortfall, vec![AnsAsset::new("atom", 10u128)]);
+
+    Ok(())
+}